@@ -1,8 +1,16 @@
-use proc_macro2::{Ident, TokenStream};
-use quote::{quote, ToTokens};
-use syn::{parenthesized, Attribute, LitInt, LitStr, Token};
+use std::cmp::Reverse;
 
-#[derive(Debug)]
+use proc_macro2::{Ident, Literal, TokenStream, TokenTree};
+use quote::{format_ident, quote, ToTokens};
+use syn::meta::ParseNestedMeta;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{
+    parenthesized, Attribute, DataEnum, Expr, ExprGroup, ExprLit, ExprParen, ExprUnary, Field, Fields, GenericArgument, Lit, LitByteStr, LitInt,
+    LitStr, Member, Path, PathArguments, Token, Type, UnOp, Variant, WherePredicate
+};
+
+#[derive(Debug, Copy, Clone)]
 pub enum Endian {
     Little,
     Big,
@@ -20,30 +28,161 @@ impl ToTokens for Endian {
     }
 }
 
-pub fn parse_top_level_attributes(attrs: &Vec<Attribute>) -> syn::Result<(Endian, bool)> {
+fn parse_endian(meta: &ParseNestedMeta) -> syn::Result<Endian> {
+    let lit = meta.value()?.parse::<LitStr>()?;
+    match lit.value().as_str() {
+        "little" => Ok(Endian::Little),
+        "big" => Ok(Endian::Big),
+        _ => Err(meta.error("endian can either be \"little\" or \"big\""))
+    }
+}
+
+/// The numbering of bits within a bitfield.
+#[derive(Debug, Default, Copy, Clone)]
+pub enum BitOrder {
+    /// Bit 0 is the least significant bit.
+    #[default]
+    Lsb0,
+    /// Bit 0 is the most significant bit, as in IETF and ITU diagrams.
+    Msb0
+}
+
+impl BitOrder {
+    /// The `BitBuffer` method that selects a range of bits in this order.
+    pub fn set_range(self) -> Ident {
+        match self {
+            BitOrder::Lsb0 => format_ident!("set_range"),
+            BitOrder::Msb0 => format_ident!("set_range_msb0")
+        }
+    }
+}
+
+fn parse_bit_order(ident: &Ident) -> syn::Result<BitOrder> {
+    match ident.to_string().as_str() {
+        "lsb0" => Ok(BitOrder::Lsb0),
+        "msb0" => Ok(BitOrder::Msb0),
+        _ => Err(syn::Error::new_spanned(ident, "bit order can either be lsb0 or msb0"))
+    }
+}
+
+/// How a bitflags type treats bits that don't correspond to a defined flag.
+#[derive(Debug, Default, Copy, Clone)]
+pub enum BitflagsMode {
+    /// Unknown bits are rejected as an invalid value.
+    #[default]
+    Strict,
+    /// Unknown bits are dropped.
+    Truncate,
+    /// Unknown bits are kept as they are.
+    Retain
+}
+
+fn parse_bitflags_mode(ident: &Ident) -> syn::Result<BitflagsMode> {
+    match ident.to_string().as_str() {
+        "strict" => Ok(BitflagsMode::Strict),
+        "truncate" => Ok(BitflagsMode::Truncate),
+        "retain" => Ok(BitflagsMode::Retain),
+        _ => Err(syn::Error::new_spanned(ident, "bitflags mode can either be strict, truncate or retain"))
+    }
+}
+
+pub struct TopLevelAttributes {
+    pub endian: Endian,
+    pub bitflags: Option<BitflagsMode>,
+    pub tag: Option<Type>,
+    pub bound: Option<Vec<WherePredicate>>,
+    pub magic: Option<LitByteStr>,
+    pub align: Option<usize>,
+    pub pad_byte: u8,
+    pub strict_padding: bool,
+    pub validate: Option<Path>,
+    pub bit_order: BitOrder
+}
+
+pub fn parse_top_level_attributes(attrs: &Vec<Attribute>) -> syn::Result<TopLevelAttributes> {
     let mut endian = Endian::Generic;
-    let mut bitflags = false;
+    let mut bitflags = None;
+    let mut tag = None;
+    let mut bound = None;
+    let mut magic = None;
+    let mut align = None;
+    let mut pad_byte = 0;
+    let mut strict_padding = false;
+    let mut validate = None;
+    let mut bit_order = BitOrder::default();
     for attr in attrs {
         if attr.path().is_ident("instructor") {
             attr.parse_nested_meta(|meta| {
                 if meta.path.is_ident("endian") {
-                    let lit = meta.value()?.parse::<LitStr>()?;
-                    return match lit.value().as_str() {
-                        "little" => Ok(Endian::Little),
-                        "big" => Ok(Endian::Big),
-                        _ => Err(meta.error("endian can either be \"little\" or \"big\""))
-                    }
-                    .map(|e| endian = e);
+                    endian = parse_endian(&meta)?;
+                    return Ok(());
                 }
                 if meta.path.is_ident("bitflags") {
-                    bitflags = true;
+                    let mut mode = BitflagsMode::default();
+                    if meta.input.peek(syn::token::Paren) {
+                        let content;
+                        parenthesized!(content in meta.input);
+                        mode = parse_bitflags_mode(&content.parse()?)?;
+                    }
+                    bitflags = Some(mode);
+                    return Ok(());
+                }
+                if meta.path.is_ident("tag") {
+                    tag = Some(meta.value()?.parse::<Type>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("magic") {
+                    magic = Some(meta.value()?.parse::<LitByteStr>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("align") {
+                    let lit = meta.value()?.parse::<LitInt>()?;
+                    match lit.base10_parse()? {
+                        0 => return Err(syn::Error::new_spanned(lit, "alignment must be greater than zero")),
+                        n => align = Some(n)
+                    }
+                    return Ok(());
+                }
+                if meta.path.is_ident("pad_byte") {
+                    pad_byte = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+                    return Ok(());
+                }
+                if meta.path.is_ident("strict_padding") {
+                    strict_padding = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("validate") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    validate = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("bit_order") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    bit_order = parse_bit_order(&lit.parse()?)?;
+                    return Ok(());
+                }
+                if meta.path.is_ident("bound") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    let predicates = lit.parse_with(Punctuated::<WherePredicate, Token![,]>::parse_terminated)?;
+                    bound = Some(predicates.into_iter().collect());
                     return Ok(());
                 }
                 Err(meta.error("unknown attribute"))
             })?;
         }
     }
-    Ok((endian, bitflags))
+    Ok(TopLevelAttributes {
+        endian,
+        bitflags,
+        tag,
+        bound,
+        magic,
+        align,
+        pad_byte,
+        strict_padding,
+        validate,
+        bit_order
+    })
 }
 
 pub fn get_repr(attrs: &Vec<Attribute>) -> syn::Result<Option<Ident>> {
@@ -59,46 +198,761 @@ pub fn get_repr(attrs: &Vec<Attribute>) -> syn::Result<Option<Ident>> {
     Ok(repr)
 }
 
-pub fn is_default(attrs: &Vec<Attribute>) -> syn::Result<bool> {
+pub struct VariantAttributes {
+    pub default: bool,
+    pub unknown: bool,
+    pub tag_value: Option<Expr>,
+    pub values: Vec<Expr>,
+    pub range: Option<VariantRange>
+}
+
+/// A range of values captured by a variant of an int-like enum, like `0xF0..=0xFF`.
+pub struct VariantRange {
+    pub start: Expr,
+    pub end: Expr,
+    pub inclusive: bool
+}
+
+impl Parse for VariantRange {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        // the start is parsed on its own, as a full expression parser would take the whole range
+        let mut start = TokenStream::new();
+        while !input.peek(Token![..]) {
+            start.extend([input.parse::<TokenTree>()?]);
+        }
+        let start = syn::parse2(start)?;
+        let inclusive = match input.parse::<Option<Token![..=]>>()? {
+            Some(_) => true,
+            None => input.parse::<Token![..]>().map(|_| false)?
+        };
+        let end = input.parse()?;
+        Ok(Self { start, end, inclusive })
+    }
+}
+
+impl ToTokens for VariantRange {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let Self { start, end, inclusive } = self;
+        tokens.extend(match inclusive {
+            true => quote! { #start..=#end },
+            false => quote! { #start..#end }
+        });
+    }
+}
+
+pub fn parse_variant_attributes(attrs: &Vec<Attribute>) -> syn::Result<VariantAttributes> {
     let mut default = false;
+    let mut unknown = None;
+    let mut tag_value = None;
+    let mut values = Vec::new();
+    let mut range = None;
     for attr in attrs {
         if attr.path().is_ident("instructor") {
             attr.parse_nested_meta(|meta| {
-                default |= meta.path.is_ident("default");
-                Ok(())
+                if meta.path.is_ident("value") {
+                    values.push(meta.value()?.parse::<Expr>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("range") {
+                    range = Some(meta.value()?.parse::<VariantRange>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("values") {
+                    let content;
+                    parenthesized!(content in meta.input);
+                    let list = Punctuated::<Expr, Token![,]>::parse_separated_nonempty(&content)?;
+                    values.extend(list);
+                    return Ok(());
+                }
+                if meta.path.is_ident("default") {
+                    default = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("unknown") {
+                    unknown = Some(meta.path.clone());
+                    return Ok(());
+                }
+                if meta.path.is_ident("tag_value") {
+                    tag_value = Some(meta.value()?.parse::<Expr>()?);
+                    return Ok(());
+                }
+                Err(meta.error("unknown attribute"))
             })?;
         }
     }
-    Ok(default)
+    if let (Some(unknown), true) = (&unknown, default) {
+        return Err(syn::Error::new_spanned(unknown, "a variant cannot be both `default` and `unknown`"));
+    }
+    if let (Some(unknown), false) = (&unknown, values.is_empty()) {
+        return Err(syn::Error::new_spanned(unknown, "unknown variants cannot have values of their own"));
+    }
+    if let (Some(range), true) = (&range, default || unknown.is_some() || !values.is_empty()) {
+        return Err(syn::Error::new_spanned(range, "variants with a range cannot be default, unknown or have other values"));
+    }
+    Ok(VariantAttributes {
+        default,
+        unknown: unknown.is_some(),
+        tag_value,
+        values,
+        range
+    })
+}
+
+/// A variant of an int-like enum together with the values that represent it.
+pub struct IntVariant<'a> {
+    pub ident: &'a Ident,
+    /// The values read as this variant, the first one is written.
+    pub values: Vec<Expr>,
+    /// The range of values captured by this variant.
+    pub range: Option<VariantRange>,
+    pub default: bool,
+    pub unknown: bool
+}
+
+/// Parses the variants of an int-like enum.
+///
+/// Unless given with `value` or `values`, a variant is represented by its discriminant.
+/// Implicit discriminants follow the rules of Rust, so they count up from the previous one.
+pub fn int_variants(data: &DataEnum) -> syn::Result<Vec<IntVariant<'_>>> {
+    let mut variants = Vec::new();
+    // the next implicit discriminant, unknown after a discriminant that is not an integer literal
+    let mut next = Some(0);
+    let mut catch_all = false;
+    // the values of every variant so far, as far as they are known at compile time
+    let mut claimed: Vec<(i128, i128, &Ident)> = Vec::new();
+    for variant in data.variants.iter() {
+        let ident = &variant.ident;
+        let attrs = parse_variant_attributes(&variant.attrs)?;
+        let discriminant = match &variant.discriminant {
+            Some((_, expr)) => {
+                next = int_literal(expr).and_then(|value| value.checked_add(1));
+                Some(expr.clone())
+            }
+            None => {
+                let current = next;
+                next = next.and_then(|value| value.checked_add(1));
+                current.map(|value| {
+                    let value = Literal::i128_unsuffixed(value);
+                    syn::parse_quote!(#value)
+                })
+            }
+        };
+        if attrs.default || attrs.unknown {
+            if catch_all {
+                return Err(syn::Error::new_spanned(ident, "only one variant can be marked as default or unknown"));
+            }
+            catch_all = true;
+        }
+        let values = if attrs.unknown || attrs.range.is_some() {
+            // these variants hold the raw value instead
+            check_capturing_variant(variant)?;
+            Vec::new()
+        } else if !attrs.values.is_empty() {
+            attrs.values
+        } else {
+            match discriminant {
+                Some(discriminant) => vec![discriminant],
+                None => return Err(syn::Error::new_spanned(ident, "variants following a non-literal discriminant need an explicit discriminant"))
+            }
+        };
+        let mut known = values.iter().filter_map(int_literal).map(|value| (value, value)).collect::<Vec<_>>();
+        if let Some(range) = &attrs.range {
+            if let Some((start, end)) = literal_range(range) {
+                if start > end {
+                    return Err(syn::Error::new_spanned(range, "ranges cannot be empty"));
+                }
+                known.push((start, end));
+            }
+        }
+        for (start, end) in known {
+            if let Some((_, _, other)) = claimed.iter().find(|(first, last, _)| start <= *last && *first <= end) {
+                return Err(syn::Error::new_spanned(ident, format!("the values of `{}` overlap with those of `{}`", ident, other)));
+            }
+            claimed.push((start, end, ident));
+        }
+        variants.push(IntVariant {
+            ident,
+            values,
+            range: attrs.range,
+            default: attrs.default,
+            unknown: attrs.unknown
+        });
+    }
+    Ok(variants)
+}
+
+/// The value of an integer literal, which can be negative.
+pub fn int_literal(expr: &Expr) -> Option<i128> {
+    match expr {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse().ok(),
+        Expr::Unary(ExprUnary { op: UnOp::Neg(_), expr, .. }) => int_literal(expr).map(|value| -value),
+        Expr::Group(ExprGroup { expr, .. }) | Expr::Paren(ExprParen { expr, .. }) => int_literal(expr),
+        _ => None
+    }
 }
 
-#[allow(clippy::type_complexity)]
-pub fn get_bitfield_start(attrs: &Vec<Attribute>) -> syn::Result<(Option<Ident>, Option<(u32, u32)>)> {
-    let mut bitfield = None;
-    let mut bitrange = None;
+/// The inclusive bounds of a range whose limits are integer literals.
+fn literal_range(range: &VariantRange) -> Option<(i128, i128)> {
+    let start = int_literal(&range.start)?;
+    let end = int_literal(&range.end)?;
+    match range.inclusive {
+        true => Some((start, end)),
+        false => Some((start, end.checked_sub(1)?))
+    }
+}
+
+/// Checks that a variant of an int-like enum that captures the raw value can hold it.
+fn check_capturing_variant(variant: &Variant) -> syn::Result<()> {
+    match &variant.fields {
+        Fields::Unnamed(fields) if fields.unnamed.len() == 1 => Ok(()),
+        _ => Err(syn::Error::new_spanned(&variant.ident, "variants capturing the raw value must have a single unnamed field to hold it"))
+    }
+}
+
+#[derive(Default)]
+pub struct FieldAttributes {
+    pub bitfield: Option<Ident>,
+    pub bit_order: Option<BitOrder>,
+    pub exhaustive: bool,
+    pub bits: Option<(u32, u32)>,
+    pub signed: bool,
+    pub bit_width: Option<u32>,
+    pub byte_align: bool,
+    pub select: Option<Member>,
+    pub endian: Option<Endian>,
+    pub count: Option<Member>,
+    pub count_prefix: Option<Type>,
+    pub byte_len: Option<Member>,
+    pub byte_len_prefix: Option<Type>,
+    pub constant: Option<Expr>,
+    pub pad_before: Option<usize>,
+    pub pad_after: Option<usize>,
+    pub condition: Option<Expr>,
+    pub calc: Option<Expr>,
+    pub verify_calc: bool,
+    pub checksum: Option<Type>,
+    pub covers: Option<(Member, Member)>,
+    pub validate: Option<Path>,
+    pub read_with: Option<Path>,
+    pub write_with: Option<Path>,
+    pub skip: bool,
+    pub default: Option<Expr>,
+    pub map: Option<Type>,
+    pub try_map: Option<Type>
+}
+
+pub fn parse_field_attributes(attrs: &Vec<Attribute>) -> syn::Result<FieldAttributes> {
+    let mut result = FieldAttributes::default();
+    let mut skip = None;
+    let mut signed = None;
+    let mut bit_width = None;
+    let mut byte_align = None;
     for attr in attrs {
         if attr.path().is_ident("instructor") {
             attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("bits") && meta.input.peek(Token![=]) {
+                    let lit = meta.value()?.parse::<LitInt>()?;
+                    let width = lit.base10_parse()?;
+                    if !(1..=64).contains(&width) {
+                        return Err(syn::Error::new_spanned(lit, "bit widths must be between 1 and 64"));
+                    }
+                    result.bit_width = Some(width);
+                    bit_width = Some(lit);
+                    return Ok(());
+                }
+                if meta.path.is_ident("byte_align") {
+                    result.byte_align = true;
+                    byte_align = Some(meta.path.clone());
+                    return Ok(());
+                }
                 if meta.path.is_ident("bits") {
                     let content;
                     parenthesized!(content in meta.input);
                     let start: u32 = content.parse::<LitInt>()?.base10_parse()?;
                     content.parse::<Token![..]>()?;
-                    let end: u32 = content.parse::<LitInt>()?.base10_parse()?;
-                    bitrange = Some((start, end));
+                    let end = content.parse::<LitInt>()?;
+                    if end.base10_parse::<u32>()? <= start {
+                        return Err(syn::Error::new_spanned(end, "bit ranges cannot be empty"));
+                    }
+                    result.bits = Some((start, end.base10_parse()?));
+                    return Ok(());
+                }
+                if meta.path.is_ident("signed") {
+                    result.signed = true;
+                    signed = Some(meta.path.clone());
                     return Ok(());
                 }
                 if meta.path.is_ident("bitfield") {
                     let content;
                     parenthesized!(content in meta.input);
                     let ident: Ident = content.parse()?;
-                    bitfield = Some(ident);
+                    result.bitfield = Some(ident);
+                    while content.parse::<Option<Token![,]>>()?.is_some() {
+                        let option: Ident = content.parse()?;
+                        match option == "exhaustive" {
+                            true => result.exhaustive = true,
+                            false => result.bit_order = Some(parse_bit_order(&option)?)
+                        }
+                    }
+                    return Ok(());
+                }
+                if meta.path.is_ident("endian") {
+                    result.endian = Some(parse_endian(&meta)?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("count") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.count = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("count_prefix") {
+                    result.count_prefix = Some(meta.value()?.parse::<Type>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("byte_len") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.byte_len = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("byte_len_prefix") {
+                    result.byte_len_prefix = Some(meta.value()?.parse::<Type>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("const") {
+                    result.constant = Some(meta.value()?.parse::<Expr>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("pad_before") {
+                    result.pad_before = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("pad_after") {
+                    result.pad_after = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("if") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.condition = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("calc") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.calc = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("verify_calc") {
+                    result.verify_calc = true;
+                    return Ok(());
+                }
+                if meta.path.is_ident("checksum") {
+                    result.checksum = Some(meta.value()?.parse::<Type>()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("covers") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.covers = Some(lit.parse_with(|input: ParseStream| {
+                        let first = input.parse::<Member>()?;
+                        input.parse::<Token![..=]>()?;
+                        let last = input.parse::<Member>()?;
+                        Ok((first, last))
+                    })?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("validate") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.validate = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("with") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    let module = lit.parse::<Path>()?;
+                    let (mut read, mut write) = (module.clone(), module);
+                    read.segments.push(format_ident!("read").into());
+                    write.segments.push(format_ident!("write").into());
+                    result.read_with = Some(read);
+                    result.write_with = Some(write);
+                    return Ok(());
+                }
+                if meta.path.is_ident("read_with") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.read_with = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("write_with") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.write_with = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("skip") {
+                    result.skip = true;
+                    skip = Some(meta.path.clone());
+                    return Ok(());
+                }
+                if meta.path.is_ident("default") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.default = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("map") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.map = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("try_map") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.try_map = Some(lit.parse()?);
+                    return Ok(());
+                }
+                if meta.path.is_ident("select") {
+                    let lit = meta.value()?.parse::<LitStr>()?;
+                    result.select = Some(lit.parse()?);
                     return Ok(());
                 }
                 Err(meta.error("unknown attribute"))
-            })
-            .unwrap();
+            })?;
+        }
+    }
+    if let (Some((first, _)), None) = (&result.covers, &result.checksum) {
+        return Err(syn::Error::new_spanned(first, "`covers` requires a checksum"));
+    }
+    if let (Some(signed), None) = (&signed, result.bits) {
+        return Err(syn::Error::new_spanned(signed, "`signed` can only be used on bitfield members"));
+    }
+    if let (Some(byte_align), None) = (&byte_align, result.bit_width) {
+        return Err(syn::Error::new_spanned(byte_align, "`byte_align` can only be used on fields with a bit width"));
+    }
+    if let (Some(default), false) = (&result.default, result.skip) {
+        return Err(syn::Error::new_spanned(default, "`default` can only be used on skipped fields"));
+    }
+    if let Some(skip) = skip {
+        let bitfield = result.bitfield.is_some() || result.bits.is_some() || result.bit_width.is_some();
+        let encoded = bitfield || result.select.is_some() || result.constant.is_some();
+        let sized = result.count.is_some() || result.count_prefix.is_some() || result.byte_len.is_some() || result.byte_len_prefix.is_some();
+        let custom = result.read_with.is_some() || result.write_with.is_some() || result.calc.is_some() || result.checksum.is_some();
+        let mapped = result.map.is_some() || result.try_map.is_some();
+        if encoded || sized || custom || mapped || result.condition.is_some() {
+            return Err(syn::Error::new_spanned(skip, "skipped fields cannot carry attributes that describe their encoding"));
         }
     }
-    Ok((bitfield, bitrange))
+    if let (Some(_), Some(try_map)) = (&result.map, &result.try_map) {
+        return Err(syn::Error::new_spanned(try_map, "`map` and `try_map` are mutually exclusive"));
+    }
+    if let Some(path) = result.read_with.as_ref().or(result.write_with.as_ref()) {
+        let counted = result.count.is_some() || result.count_prefix.is_some();
+        if result.select.is_some() || counted || result.byte_len.is_some() || result.byte_len_prefix.is_some() {
+            return Err(syn::Error::new_spanned(path, "custom read and write functions cannot be combined with tags, counts or lengths"));
+        }
+    }
+    if let Some(bit_width) = bit_width {
+        let bitfield = result.bitfield.is_some() || result.bits.is_some();
+        let sized = result.count.is_some() || result.count_prefix.is_some() || result.byte_len.is_some() || result.byte_len_prefix.is_some();
+        let custom = result.read_with.is_some() || result.write_with.is_some() || result.checksum.is_some() || result.select.is_some();
+        let padded = result.pad_before.is_some() || result.pad_after.is_some();
+        if bitfield || sized || custom || padded {
+            let message = "fields with a bit width cannot be bitfield members, tagged, sized, padded or use custom functions";
+            return Err(syn::Error::new_spanned(bit_width, message));
+        }
+    }
+    if let Some(condition) = &result.condition {
+        let counted = result.count.is_some() || result.count_prefix.is_some();
+        if result.bits.is_some() || result.select.is_some() || counted || result.byte_len_prefix.is_some() {
+            return Err(syn::Error::new_spanned(condition, "conditional fields cannot be bitfield members, tagged, counted or length prefixed"));
+        }
+    }
+    Ok(result)
+}
+
+/// The number of bits provided by a bitfield storage type, if it is one of the built-in ones.
+fn storage_bits(storage: &Ident) -> Option<u32> {
+    match storage.to_string().as_str() {
+        "u8" => Some(8),
+        "u16" => Some(16),
+        "u24" => Some(24),
+        "u32" => Some(32),
+        "u64" => Some(64),
+        "u128" => Some(128),
+        _ => None
+    }
+}
+
+/// The bit ranges claimed by the members of a bitfield so far.
+struct BitfieldLayout<'a> {
+    storage: &'a Ident,
+    exhaustive: bool,
+    members: Vec<(u32, u32)>
+}
+
+/// Checks that the members of every bitfield fit into its storage and don't overlap.
+pub fn check_bitfields(fields: &Fields, attributes: &[FieldAttributes]) -> syn::Result<()> {
+    let mut bitfield: Option<BitfieldLayout> = None;
+    for (field, attrs) in fields.iter().zip(attributes) {
+        if attrs.bits.is_none() || attrs.bitfield.is_some() {
+            if let Some(done) = bitfield.take() {
+                check_coverage(done)?;
+            }
+        }
+        if let Some(storage) = &attrs.bitfield {
+            bitfield = Some(BitfieldLayout {
+                storage,
+                exhaustive: attrs.exhaustive,
+                members: Vec::new()
+            });
+        }
+        // members without a bitfield are reported during code generation
+        if let (Some((start, end)), Some(layout)) = (attrs.bits, &mut bitfield) {
+            if let Some(width) = storage_bits(layout.storage).filter(|width| end > *width) {
+                let message = format!("bits {}..{} exceed the {} bits of `{}`", start, end, width, layout.storage);
+                return Err(syn::Error::new_spanned(field, message));
+            }
+            if let Some((first, last)) = layout.members.iter().find(|(first, last)| start < *last && *first < end) {
+                let message = format!("bits {}..{} overlap with bits {}..{}", start, end, first, last);
+                return Err(syn::Error::new_spanned(field, message));
+            }
+            layout.members.push((start, end));
+        }
+    }
+    match bitfield {
+        Some(last) => check_coverage(last),
+        None => Ok(())
+    }
+}
+
+/// Exhaustive bitfields have to assign every bit of their storage to a member.
+fn check_coverage(BitfieldLayout { storage, exhaustive, mut members }: BitfieldLayout) -> syn::Result<()> {
+    if !exhaustive {
+        return Ok(());
+    }
+    let width = storage_bits(storage).ok_or_else(|| syn::Error::new_spanned(storage, "exhaustive bitfields require a built-in storage type"))?;
+    members.sort_unstable();
+    let mut gaps = Vec::new();
+    let mut next = 0;
+    for (start, end) in members.into_iter().chain([(width, width)]) {
+        if start > next {
+            gaps.push(format!("{}..{}", next, start));
+        }
+        next = end;
+    }
+    match gaps.is_empty() {
+        true => Ok(()),
+        false => Err(syn::Error::new_spanned(storage, format!("bits {} are not assigned to any field", gaps.join(", "))))
+    }
+}
+
+/// Rejects bit widths that exceed the primitive type a field is read as.
+/// Other types are responsible for checking the width themselves.
+pub fn check_bit_width(field: &Field, attrs: &FieldAttributes) -> syn::Result<()> {
+    let Some(width) = attrs.bit_width else {
+        return Ok(());
+    };
+    let ty = match (attrs.map.as_ref().or(attrs.try_map.as_ref()), attrs.condition.is_some()) {
+        (Some(wire), _) => wire,
+        (None, true) => option_inner(&field.ty).unwrap_or(&field.ty),
+        (None, false) => &field.ty
+    };
+    let bits = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().map(Ident::to_string),
+        _ => None
+    };
+    let bits = match bits.as_deref() {
+        Some("bool") => 1,
+        Some("u8" | "i8") => 8,
+        Some("u16" | "i16") => 16,
+        Some("u32" | "i32") => 32,
+        Some("u64" | "i64") => 64,
+        _ => return Ok(())
+    };
+    match width > bits {
+        true => Err(syn::Error::new_spanned(ty, format!("{} bits do not fit into `{}`", width, ty.to_token_stream()))),
+        false => Ok(())
+    }
+}
+
+/// The `T` of an `Option<T>`.
+fn option_inner(ty: &Type) -> Option<&Type> {
+    let Type::Path(path) = ty else {
+        return None;
+    };
+    let segment = path.path.segments.last().filter(|segment| segment.ident == "Option")?;
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if args.args.len() == 1 => match args.args.first() {
+            Some(GenericArgument::Type(inner)) => Some(inner),
+            _ => None
+        },
+        _ => None
+    }
+}
+
+/// Whether a bitfield member is stored in two's complement and has to be sign-extended.
+/// Signed integers are recognized by name, other types can opt in with `signed`.
+pub fn signed_member(field: &Field, attrs: &FieldAttributes) -> syn::Result<bool> {
+    let ty = attrs.map.as_ref().or(attrs.try_map.as_ref()).unwrap_or(&field.ty);
+    let name = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().map(Ident::to_string),
+        _ => None
+    };
+    match (name.as_deref(), attrs.bits) {
+        (Some("bool"), Some((start, end))) if end != start + 1 => {
+            Err(syn::Error::new_spanned(ty, "`bool` bitfield members must be a single bit wide"))
+        }
+        (Some("i8" | "i16" | "i32" | "i64" | "i128"), _) => Ok(true),
+        _ => Ok(attrs.signed)
+    }
+}
+
+/// The name of the local variable that holds the value of a field.
+pub fn local_ident(index: usize, field: &Field) -> Ident {
+    field
+        .ident
+        .clone()
+        .unwrap_or_else(|| format_ident!("field_{}", index))
+}
+
+pub fn find_field<'a>(fields: &'a Fields, member: &Member) -> syn::Result<(usize, &'a Field)> {
+    fields
+        .iter()
+        .enumerate()
+        .find(|(i, field)| match (member, &field.ident) {
+            (Member::Named(name), Some(ident)) => name == ident,
+            (Member::Unnamed(index), None) => index.index as usize == *i,
+            _ => false
+        })
+        .ok_or_else(|| syn::Error::new_spanned(member, "unknown field"))
+}
+
+/// A checksum field together with the inclusive range of fields it covers.
+pub struct ChecksumRange {
+    pub first: usize,
+    pub last: usize,
+    pub field: usize,
+    pub algorithm: Type
+}
+
+impl ChecksumRange {
+    /// Whether the checksum field is part of the range, in which case it counts as zero.
+    pub fn covers_itself(&self) -> bool {
+        self.first <= self.field && self.field <= self.last
+    }
+
+    /// Whether `inner` is computed while this checksum's own field passes by,
+    /// so that it would see the field before its value is known.
+    fn encloses_field_of(&self, inner: &ChecksumRange) -> bool {
+        let nested = self.first <= inner.first && inner.last <= self.last;
+        let same = (self.first, self.last) == (inner.first, inner.last);
+        self.covers_itself() && nested && inner.first <= self.field && self.field <= inner.last && (!same || inner.covers_itself())
+    }
+}
+
+/// Collects the checksum fields of a struct.
+///
+/// Unless specified otherwise a checksum covers all preceding fields.
+/// The covered fields have to precede the checksum or include it, ranges must either be disjoint or nested
+/// and they can neither start nor end in the middle of a bitfield.
+pub fn checksum_ranges(fields: &Fields, attributes: &[FieldAttributes]) -> syn::Result<Vec<ChecksumRange>> {
+    let mut ranges: Vec<ChecksumRange> = Vec::new();
+    for (i, (field, attrs)) in fields.iter().zip(attributes).enumerate() {
+        let algorithm = match &attrs.checksum {
+            Some(algorithm) => algorithm.clone(),
+            None => continue
+        };
+        if attrs.bits.is_some() {
+            return Err(syn::Error::new_spanned(field, "a checksum cannot be part of a bitfield"));
+        }
+        let (first, last) = match &attrs.covers {
+            Some((first, last)) => (find_field(fields, first)?.0, find_field(fields, last)?.0),
+            None if i > 0 => (0, i - 1),
+            None => return Err(syn::Error::new_spanned(field, "a checksum must be preceded by the fields it covers"))
+        };
+        if first > last || first > i {
+            return Err(syn::Error::new_spanned(field, "a checksum must cover a non-empty range of fields that does not start after it"));
+        }
+        let nested = |range: &ChecksumRange| {
+            last < range.first || range.last < first || (range.first <= first && last <= range.last) || (first <= range.first && range.last <= last)
+        };
+        if !ranges.iter().all(nested) {
+            return Err(syn::Error::new_spanned(field, "checksum ranges must either be disjoint or nested"));
+        }
+        for index in [first, last + 1].into_iter().filter(|index| *index < attributes.len()) {
+            if attributes[index].bits.is_some() && attributes[index].bitfield.is_none() {
+                return Err(syn::Error::new_spanned(field, "a checksum range cannot start or end inside a bitfield"));
+            }
+            if index > 0 && attributes[index].bit_width.is_some() && attributes[index - 1].bit_width.is_some() {
+                return Err(syn::Error::new_spanned(field, "a checksum range cannot start or end inside a bit stream"));
+            }
+        }
+        let range = ChecksumRange { first, last, field: i, algorithm };
+        if ranges.iter().any(|other| other.encloses_field_of(&range) || range.encloses_field_of(other)) {
+            return Err(syn::Error::new_spanned(field, "a checksum covering its own field cannot be covered by a checksum nested in its range"));
+        }
+        ranges.push(range);
+    }
+    Ok(ranges)
+}
+
+/// Finishes the checksums whose ranges end right before field `index` and starts those beginning with it.
+///
+/// While a checksum is computed `buffer` is shadowed by a wrapper that passes every byte through it,
+/// so nested ranges are finished in the reverse order in which they were started.
+/// When writing, `field_endians` is given and a checksum covering its own field collects the covered bytes
+/// instead, so that the checksum can be filled in before they are passed on.
+pub fn checksum_boundary(ranges: &[ChecksumRange], index: usize, field_endians: Option<&[Endian]>) -> TokenStream {
+    let mut statements = TokenStream::new();
+    let mut ending = ranges.iter().filter(|range| range.last + 1 == index).collect::<Vec<_>>();
+    ending.sort_by_key(|range| Reverse((range.first, range.covers_itself(), range.field)));
+    for range in ending {
+        let digest = format_ident!("___instructor_digest_{}", range.field);
+        let checksum = checksum_ident(range.field);
+        statements.extend(match field_endians {
+            Some(endians) if range.covers_itself() => {
+                let (covered, outer, position) = covered_idents(range.field);
+                let algorithm = &range.algorithm;
+                let endian = endians[range.field];
+                quote! {
+                    let buffer = #outer;
+                    let #checksum = <#algorithm as instructor::checksum::Checksum>::checksum(&#covered);
+                    let mut ___instructor_patch = ::std::vec::Vec::<u8>::new();
+                    instructor::Instruct::<#endian>::write_to_buffer(&#checksum, &mut ___instructor_patch);
+                    #covered[#position..#position + ___instructor_patch.len()].copy_from_slice(&___instructor_patch);
+                    instructor::BufferMut::extend_from_slice(buffer, &#covered);
+                }
+            }
+            _ => quote! {
+                let (buffer, #checksum) = #digest.finish();
+            }
+        });
+    }
+    let mut starting = ranges.iter().filter(|range| range.first == index).collect::<Vec<_>>();
+    starting.sort_by_key(|range| (Reverse(range.last), range.covers_itself(), range.field));
+    for range in starting {
+        let digest = format_ident!("___instructor_digest_{}", range.field);
+        let algorithm = &range.algorithm;
+        statements.extend(match field_endians {
+            Some(_) if range.covers_itself() => {
+                let (covered, outer, _) = covered_idents(range.field);
+                quote! {
+                    let mut #covered = ::std::vec::Vec::<u8>::new();
+                    let #outer = buffer;
+                    let buffer = &mut #covered;
+                }
+            }
+            _ => quote! {
+                let mut #digest = instructor::checksum::Digest::<_, #algorithm>::new(buffer);
+                let buffer = &mut #digest;
+            }
+        });
+    }
+    statements
+}
+
+/// The bytes collected for a checksum covering its own field, the buffer they are passed on to
+/// and the position of the checksum within them.
+pub fn covered_idents(field: usize) -> (Ident, Ident, Ident) {
+    (
+        format_ident!("___instructor_covered_{}", field),
+        format_ident!("___instructor_outer_{}", field),
+        format_ident!("___instructor_position_{}", field)
+    )
+}
+
+pub fn checksum_ident(index: usize) -> Ident {
+    format_ident!("___instructor_checksum_{}", index)
 }
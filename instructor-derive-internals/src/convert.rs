@@ -0,0 +1,100 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::{Data, DeriveInput};
+
+use crate::attr::{get_repr, int_variants};
+use crate::pack::to_repr;
+use crate::unpack::from_repr;
+
+/// Implements `TryFrom<repr>` and `From<Enum>` for `repr` for an int-like enum,
+/// together with `VARIANTS`, listing every variant that does not hold a raw value.
+pub fn derive_int_enum(input: DeriveInput) -> syn::Result<TokenStream> {
+    let DeriveInput { ident, data, attrs, generics, .. } = input;
+
+    let data = match data {
+        Data::Enum(data) => data,
+        _ => return Err(syn::Error::new_spanned(ident, "only int-like enums can be converted"))
+    };
+    let repr = match get_repr(&attrs)? {
+        Some(repr) => repr,
+        None => return Err(syn::Error::new_spanned(ident, "int-like enums must have a repr attribute"))
+    };
+    let variants = int_variants(&data)?;
+    let units = variants
+        .iter()
+        .filter(|variant| variant.range.is_none() && !variant.unknown)
+        .map(|variant| variant.ident);
+    let from_repr = from_repr(&repr, &variants);
+    let to_repr = to_repr(&ident, &variants, quote! { value }, false);
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let output = quote! {
+        #[automatically_derived]
+        impl #impl_generics core::convert::TryFrom<#repr> for #ident #ty_generics #where_clause {
+            type Error = instructor::Error;
+
+            #[inline]
+            fn try_from(value: #repr) -> core::result::Result<Self, instructor::Error> {
+                #from_repr
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics core::convert::From<&#ident #ty_generics> for #repr #where_clause {
+            #[inline]
+            fn from(value: &#ident #ty_generics) -> Self {
+                #to_repr
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics core::convert::From<#ident #ty_generics> for #repr #where_clause {
+            #[inline]
+            fn from(value: #ident #ty_generics) -> Self {
+                Self::from(&value)
+            }
+        }
+
+        #[automatically_derived]
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Every variant that does not hold a raw value, in the order of their declaration.
+            pub const VARIANTS: &'static [Self] = &[#(Self::#units),*];
+        }
+    };
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn print_int_enum() {
+        let input = syn::parse_quote! {
+            #[repr(u8)]
+            enum Code {
+                Ping = 0x01,
+                #[instructor(values(0x02, 0x03))]
+                Pong,
+                #[instructor(range = 0x20..RESERVED_END)]
+                Reserved(u8),
+                #[instructor(unknown)]
+                Other(u8)
+            }
+        };
+
+        let output = derive_int_enum(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn reject_enums_without_repr() {
+        let input = syn::parse_quote! {
+            enum Code {
+                Ping = 0x01
+            }
+        };
+
+        assert_eq!(derive_int_enum(input).unwrap_err().to_string(), "int-like enums must have a repr attribute");
+    }
+}
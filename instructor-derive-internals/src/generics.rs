@@ -0,0 +1,50 @@
+use proc_macro2::TokenStream;
+use quote::{quote, ToTokens};
+use syn::{parse_quote, GenericParam, Generics, WherePredicate};
+
+use crate::attr::Endian;
+
+pub struct ImplGenerics {
+    pub impl_generics: TokenStream,
+    pub ty_generics: TokenStream,
+    pub where_clause: TokenStream
+}
+
+/// Combines the generics of the input type with the endian parameter of the generated impl.
+///
+/// Unless `bound` overrides them, every type parameter is required to implement `bound_trait`.
+pub fn impl_generics(
+    generics: &Generics, endian: &Endian, bound_trait: TokenStream, bound: Option<&Vec<WherePredicate>>
+) -> syn::Result<ImplGenerics> {
+    let mut extended = generics.clone();
+    if let Endian::Generic = endian {
+        if let Some(param) = generics.type_params().find(|p| p.ident == "E") {
+            return Err(syn::Error::new_spanned(
+                param,
+                "the type parameter `E` is reserved for the endianness, consider renaming it or specifying an explicit endian"
+            ));
+        }
+        extended.params.push(parse_quote!(E: instructor::Endian));
+    }
+    let where_clause = extended.make_where_clause();
+    match bound {
+        Some(bound) => where_clause.predicates.extend(bound.iter().cloned()),
+        None => {
+            for param in generics.params.iter() {
+                if let GenericParam::Type(param) = param {
+                    let ident = &param.ident;
+                    where_clause
+                        .predicates
+                        .push(parse_quote!(#ident: #bound_trait<#endian>));
+                }
+            }
+        }
+    }
+    let (impl_generics, _, where_clause) = extended.split_for_impl();
+    let (_, ty_generics, _) = generics.split_for_impl();
+    Ok(ImplGenerics {
+        impl_generics: impl_generics.to_token_stream(),
+        ty_generics: ty_generics.to_token_stream(),
+        where_clause: where_clause.map(|w| w.to_token_stream()).unwrap_or_else(|| quote! {})
+    })
+}
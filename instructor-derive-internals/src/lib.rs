@@ -1,6 +1,9 @@
 pub mod attr;
+mod convert;
+mod generics;
 mod pack;
 mod unpack;
 
+pub use convert::derive_int_enum;
 pub use pack::derive_pack;
 pub use unpack::derive_unpack;
@@ -1,34 +1,37 @@
-use proc_macro2::{Ident, TokenStream};
+use proc_macro2::{Ident, TokenStream, TokenTree};
 use quote::{format_ident, quote, ToTokens};
-use syn::{Data, DataEnum, DataStruct, DeriveInput, Fields, Index};
+use syn::{Data, DataEnum, DataStruct, DeriveInput, Field, Fields, Index, Path, Type};
 
-use crate::attr::{get_bitfield_start, get_repr, parse_top_level_attributes, Endian};
+use crate::attr::{
+    check_bit_width, check_bitfields, checksum_boundary, checksum_ident, checksum_ranges, covered_idents, find_field, get_repr, int_variants,
+    local_ident, parse_field_attributes, parse_top_level_attributes, parse_variant_attributes, signed_member, Endian, FieldAttributes,
+    IntVariant, TopLevelAttributes
+};
+use crate::generics::{impl_generics, ImplGenerics};
 
 pub fn derive_pack(input: DeriveInput) -> syn::Result<TokenStream> {
-    let DeriveInput { ident, data, attrs, .. } = input;
+    let DeriveInput { ident, data, attrs, generics, .. } = input;
 
-    let (endian, bitflags) = parse_top_level_attributes(&attrs)?;
-    if bitflags {
-        return generate_bitflags_impl(endian, ident);
+    let top = parse_top_level_attributes(&attrs)?;
+    let generics = impl_generics(&generics, &top.endian, quote! { instructor::Instruct }, top.bound.as_ref())?;
+    if top.bitflags.is_some() {
+        return generate_bitflags_impl(top.endian, generics, ident);
     }
     match data {
-        Data::Struct(data) => generate_struct_impl(endian, ident, data),
-        Data::Enum(data) => match get_repr(&attrs)? {
-            Some(repr) => generate_int_enum_impl(endian, repr, ident, data),
-            None => generate_data_enum_impl(endian, ident, data)
+        Data::Struct(data) => generate_struct_impl(top, generics, ident, data),
+        Data::Enum(data) => match (top.tag, get_repr(&attrs)?) {
+            (None, Some(repr)) => generate_int_enum_impl(top.endian, generics, repr, ident, data),
+            (tag, _) => generate_data_enum_impl(top.endian, generics, tag, ident, data)
         },
         Data::Union(_) => Err(syn::Error::new_spanned(ident, "unions are not supported"))
     }
 }
 
-fn generate_bitflags_impl(endian: Endian, ident: Ident) -> syn::Result<TokenStream> {
-    let generic = match endian {
-        Endian::Generic => quote! { <E: instructor::Endian> },
-        _ => quote! {}
-    };
+fn generate_bitflags_impl(endian: Endian, generics: ImplGenerics, ident: Ident) -> syn::Result<TokenStream> {
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
     let output = quote! {
         #[automatically_derived]
-        impl #generic instructor::Instruct<#endian> for #ident {
+        impl #impl_generics instructor::Instruct<#endian> for #ident #ty_generics #where_clause {
             #[inline]
             fn write_to_buffer<B: instructor::BufferMut>(&self, buffer: &mut B) {
                 instructor::Instruct::<#endian>::write_to_buffer(&self.bits(), buffer)
@@ -38,106 +41,553 @@ fn generate_bitflags_impl(endian: Endian, ident: Ident) -> syn::Result<TokenStre
     Ok(output)
 }
 
-fn generate_struct_impl(endian: Endian, ident: Ident, data: DataStruct) -> syn::Result<TokenStream> {
+fn generate_struct_impl(top: TopLevelAttributes, generics: ImplGenerics, ident: Ident, data: DataStruct) -> syn::Result<TokenStream> {
+    let endian = top.endian;
+    let attributes = data
+        .fields
+        .iter()
+        .map(|field| parse_field_attributes(&field.attrs))
+        .collect::<syn::Result<Vec<_>>>()?;
+    let type_name = ident.to_string();
+    let mut validations = Vec::new();
+    let mut checks = Vec::new();
+    let mut encoded = Vec::new();
+    // collections whose length is stored in another field
+    let mut counted = vec![Vec::new(); attributes.len()];
+    // payloads whose encoded size is stored in another field
+    let mut measured = vec![None; attributes.len()];
+    for (i, (field, attrs)) in data.fields.iter().zip(&attributes).enumerate() {
+        if let Some(count) = &attrs.count {
+            let (index, _) = find_field(&data.fields, count)?;
+            counted[index].push(member(i, field));
+        }
+        let len_ty = match &attrs.byte_len {
+            Some(len) => Some(&find_field(&data.fields, len)?.1.ty),
+            None => attrs.byte_len_prefix.as_ref()
+        };
+        if let Some(len_ty) = len_ty {
+            // the payload has to be encoded up front, as its size is written before it
+            let ident = member(i, field);
+            let endian = attrs.endian.unwrap_or(endian);
+            let bytes = format_ident!("___instructor_encoded_{}", i);
+            let value = map_value(attrs, quote! { &self.#ident });
+            encoded.push(quote! {
+                let mut #bytes = ::std::vec::Vec::<u8>::new();
+                instructor::Instruct::<#endian>::write_to_buffer(#value, &mut #bytes);
+            });
+            // the size has to fit into the field storing it
+            let name = ident.to_string();
+            validations.push(quote! {
+                if <#len_ty as core::convert::TryFrom<usize>>::try_from(instructor::utils::encoded_len::<#endian, _>(#value)).is_err() {
+                    return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue).in_field(#type_name, #name));
+                }
+            });
+            if let Some(len) = &attrs.byte_len {
+                let (index, _) = find_field(&data.fields, len)?;
+                if measured[index].replace((ident, bytes)).is_some() {
+                    return Err(syn::Error::new_spanned(len, "this field already stores the length of another field"));
+                }
+            }
+        }
+    }
+
+    // computed fields can refer to every other field by name
+    let patterns = data.fields.iter().enumerate().map(|(i, field)| match &field.ident {
+        Some(ident) => quote! { #ident },
+        None => {
+            let (index, local) = (Index::from(i), local_ident(i, field));
+            quote! { #index: #local }
+        }
+    });
+    let bindings = quote! {
+        #[allow(unused_variables)]
+        let Self { #(#patterns),* } = self;
+    };
+
+    check_bitfields(&data.fields, &attributes)?;
+    let checksums = checksum_ranges(&data.fields, &attributes)?;
+    let field_endians = attributes.iter().map(|attrs| attrs.endian.unwrap_or(endian)).collect::<Vec<_>>();
     let mut bitfield_ident = None;
+    let mut bitfield_storage = None;
+    let mut bit_order = top.bit_order;
+    let mut bit_stream = false;
     let mut statements = Vec::new();
-    for (i, field) in data.fields.iter().enumerate() {
-        let ident = field
-            .ident
-            .as_ref()
-            .map(|i| i.to_token_stream())
-            .unwrap_or_else(|| Index::from(i).to_token_stream());
-        let (bitfield, bitrange) = get_bitfield_start(&field.attrs)?;
-        if let Some(bitfield) = bitfield {
-            if let Some(bitfield) = bitfield_ident.take() {
+    let fill = top.pad_byte;
+    if top.align.is_some() {
+        statements.push(quote! {
+            let buffer = &mut instructor::utils::Counter::new(buffer);
+        });
+    }
+    if let Some(magic) = top.magic {
+        statements.push(quote! {
+            instructor::BufferMut::extend_from_slice(buffer, #magic);
+        });
+    }
+    for (i, (field, attrs)) in data.fields.iter().zip(attributes).enumerate() {
+        let ident = member(i, field);
+        let ty = &field.ty;
+        let field_endian = attrs.endian.unwrap_or(endian);
+        check_bit_width(field, &attrs)?;
+        let signed = signed_member(field, &attrs)?;
+        if attrs.calc.is_some() && (!counted[i].is_empty() || measured[i].is_some()) {
+            return Err(syn::Error::new_spanned(field, "this field is already filled in automatically"));
+        }
+        let name = ident.to_string();
+        if let Some(condition) = &attrs.condition {
+            // the condition is evaluated like when reading, with the fields it refers to bound by value
+            let mut referenced = Vec::new();
+            referenced_fields(condition.to_token_stream(), &data.fields, &mut referenced);
+            let present = quote! {
+                let ___instructor_present = {
+                    #(let #referenced = core::clone::Clone::clone(&self.#referenced);)*
+                    let ___instructor_condition: bool = #condition;
+                    ___instructor_condition == core::option::Option::is_some(&self.#ident)
+                };
+            };
+            validations.push(quote! {
+                #present
+                if !___instructor_present {
+                    return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue).in_field(#type_name, #name));
+                }
+            });
+            // validation is the place to reject values, this only guards against writing corrupted data
+            let message = format!("`{}` must be present exactly if its condition holds", ident);
+            checks.push(quote! {
+                #present
+                assert!(___instructor_present, #message);
+            });
+        }
+        if let Some(validate) = &attrs.validate {
+            validations.push(quote! {
+                #validate(&self.#ident).map_err(|e| Into::<instructor::Error>::into(e).in_field(#type_name, #name))?;
+            });
+        }
+        let covered = checksums.iter().find(|range| range.field == i && range.covers_itself());
+        let value = if covered.is_some() {
+            // the checksum is computed as if the field was zero and filled in once its range is complete
+            quote! { &<#ty as core::default::Default>::default() }
+        } else if attrs.checksum.is_some() {
+            let checksum = checksum_ident(i);
+            quote! { &#checksum }
+        } else if let Some((collection, others)) = counted[i].split_first() {
+            let message = format!("`{}` has too many elements to be counted by `{}`", collection, ident);
+            let collection_name = collection.to_string();
+            validations.push(quote! {
+                if <#ty as core::convert::TryFrom<usize>>::try_from(self.#collection.len()).is_err() {
+                    return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue).in_field(#type_name, #collection_name));
+                }
+            });
+            for other in others {
+                let message = format!("`{}` and `{}` must have the same length", collection, other);
+                let other_name = other.to_string();
+                validations.push(quote! {
+                    if self.#collection.len() != self.#other.len() {
+                        return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue).in_field(#type_name, #other_name));
+                    }
+                });
+                checks.push(quote! {
+                    assert_eq!(self.#collection.len(), self.#other.len(), #message);
+                });
+            }
+            quote! { &<#ty as core::convert::TryFrom<usize>>::try_from(self.#collection.len()).unwrap_or_else(|_| panic!(#message)) }
+        } else if let Some((payload, bytes)) = &measured[i] {
+            let message = format!("`{}` is too large for its length to fit into `{}`", payload, ident);
+            quote! { &<#ty as core::convert::TryFrom<usize>>::try_from(#bytes.len()).unwrap_or_else(|_| panic!(#message)) }
+        } else if let Some(calc) = &attrs.calc {
+            quote! {
+                &{
+                    #bindings
+                    let value: #ty = #calc;
+                    value
+                }
+            }
+        } else if let Some(constant) = &attrs.constant {
+            quote! { &{ let value: #ty = #constant; value } }
+        } else {
+            let bits = attrs.bits.is_some() || attrs.bit_width.is_some();
+            if !bits && attrs.write_with.is_none() && !attrs.skip {
+                // the stored value is written as is, so it has to be valid itself
+                let value = map_value(&attrs, quote! { &self.#ident });
+                validations.push(match attrs.select {
+                    Some(_) => quote! {
+                        instructor::InstructTagged::<#field_endian>::validate(#value).map_err(|e| e.in_field(#type_name, #name))?;
+                    },
+                    None => quote! {
+                        instructor::Instruct::<#field_endian>::validate(#value).map_err(|e| e.in_field(#type_name, #name))?;
+                    }
+                });
+            }
+            quote! { &self.#ident }
+        };
+        let value = map_value(&attrs, value);
+        if attrs.bits.is_some() && (attrs.pad_before.is_some() || attrs.pad_after.is_some()) {
+            return Err(syn::Error::new_spanned(field, "bitfield members cannot be padded"));
+        }
+        if attrs.bits.is_none() || attrs.bitfield.is_some() {
+            if let Some((bitfield, endian)) = bitfield_ident.take() {
                 statements.push(quote! {
                     instructor::Instruct::<#endian>::write_to_buffer(&#bitfield, buffer);
                 });
             }
+        }
+        if attrs.bit_width.is_none() && bit_stream {
+            statements.push(finish_bit_stream());
+            bit_stream = false;
+        }
+        statements.push(checksum_boundary(&checksums, i, Some(&field_endians)));
+        if attrs.bit_width.is_some() && !bit_stream {
+            // consecutive fields with a bit width share a writer that shadows `buffer`
+            statements.push(quote! {
+                let mut ___instructor_bits = instructor::BitWriter::new(buffer);
+                let buffer = &mut ___instructor_bits;
+            });
+            bit_stream = true;
+        }
+        if let Some(bitfield) = attrs.bitfield {
             let ident = quote! { ___instructor_bitfield };
             statements.push(quote! {
                 let mut #ident = instructor::BitBuffer::<#bitfield>::empty();
             });
-            bitfield_ident = Some(ident);
+            bitfield_ident = Some((ident, field_endian));
+            bitfield_storage = Some(bitfield);
+            bit_order = attrs.bit_order.unwrap_or(top.bit_order);
         }
-        if let Some((start, end)) = bitrange {
-            match bitfield_ident.as_ref() {
-                Some(bitfield_ident) => {
+        if let Some((start, end)) = attrs.bits {
+            match (bitfield_ident.as_ref(), bitfield_storage.as_ref()) {
+                (Some((bitfield_ident, _)), Some(storage)) => {
+                    let set_range = bit_order.set_range();
+                    // values that don't fit into their bits are rejected before anything is written
+                    let check = quote! { ___instructor_check };
+                    // members are big endian numbers, unless their type only supports another byte order
+                    let member_endian = attrs.endian.map_or_else(|| quote! { instructor::BigEndian }, |endian| quote! { #endian });
+                    let write = write_value(attrs.write_with.as_ref(), &member_endian, value.clone(), quote! { &mut #check });
+                    let set_signed = signed.then(|| quote! { #check.set_signed(); });
+                    let reorder = attrs.endian.map(|endian| quote! { #check.set_endian::<#endian>(); });
+                    if attrs.write_with.is_none() {
+                        validations.push(quote! {
+                            instructor::Instruct::<#member_endian>::validate(#value).map_err(|e| e.in_field(#type_name, #name))?;
+                        });
+                    }
+                    validations.push(quote! {
+                        let mut #check = instructor::BitBuffer::<#storage>::checked();
+                        #check.#set_range(#start, #end);
+                        #set_signed
+                        #reorder
+                        #write;
+                        if #check.overflowed() {
+                            return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue).in_field(#type_name, #name));
+                        }
+                    });
+                    let set_signed = signed.then(|| quote! { #bitfield_ident.set_signed(); });
+                    let reorder = attrs.endian.map(|endian| quote! { #bitfield_ident.set_endian::<#endian>(); });
+                    let write = write_value(attrs.write_with.as_ref(), member_endian, value, quote! { &mut #bitfield_ident });
                     statements.push(quote! {
-                        #bitfield_ident.set_range(#start, #end);
-                        instructor::Instruct::<instructor::BigEndian>::write_to_buffer(&self.#ident, &mut #bitfield_ident);
+                        #bitfield_ident.#set_range(#start, #end);
+                        #set_signed
+                        #reorder
+                        #write;
                     });
                 }
-                None => return Err(syn::Error::new_spanned(field, "bitfield range without bitfield"))
+                _ => return Err(syn::Error::new_spanned(field, "bitfield range without bitfield"))
             }
         } else {
-            if let Some(bitfield) = bitfield_ident.take() {
+            if let Some((bitfield, endian)) = bitfield_ident.take() {
                 statements.push(quote! {
                     instructor::Instruct::<#endian>::write_to_buffer(&#bitfield, buffer);
                 });
             }
-            statements.push(quote! {
-                instructor::Instruct::<#endian>::write_to_buffer(&self.#ident, buffer);
-            });
+            if let Some(n) = attrs.pad_before {
+                statements.push(quote! {
+                    instructor::BufferMut::pad(buffer, #n, #fill);
+                });
+            }
+            if covered.is_some() {
+                let (_, _, position) = covered_idents(i);
+                statements.push(quote! {
+                    let #position = buffer.len();
+                });
+            }
+            if attrs.skip {
+                // skipped fields only exist in memory
+            } else if let Some(select) = attrs.select {
+                let (index, tag_field) = find_field(&data.fields, &select)?;
+                let tag = member(index, tag_field);
+                let message = format!("`{}` does not match the tag stored in `{}`", ident, tag);
+                validations.push(quote! {
+                    if !instructor::InstructTagged::<#field_endian>::matches_tag(&self.#ident, &self.#tag) {
+                        return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue).in_field(#type_name, #name));
+                    }
+                });
+                // validation is the place to reject values, this only guards against writing corrupted data
+                checks.push(quote! {
+                    assert!(instructor::InstructTagged::<#field_endian>::matches_tag(&self.#ident, &self.#tag), #message);
+                });
+                statements.push(quote! {
+                    instructor::InstructTagged::<#field_endian>::write_untagged_to_buffer(#value, buffer);
+                });
+            } else if let Some(prefix) = attrs.count_prefix {
+                let message = format!("`{}` has too many elements for its count prefix", ident);
+                validations.push(quote! {
+                    if <#prefix as core::convert::TryFrom<usize>>::try_from(self.#ident.len()).is_err() {
+                        return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue).in_field(#type_name, #name));
+                    }
+                });
+                statements.push(quote! {
+                    let count = <#prefix as core::convert::TryFrom<usize>>::try_from(self.#ident.len()).unwrap_or_else(|_| panic!(#message));
+                    instructor::Instruct::<#field_endian>::write_to_buffer(&count, buffer);
+                    instructor::Instruct::<#field_endian>::write_to_buffer(#value, buffer);
+                });
+            } else if attrs.byte_len.is_some() {
+                let bytes = format_ident!("___instructor_encoded_{}", i);
+                statements.push(quote! {
+                    instructor::BufferMut::extend_from_slice(buffer, &#bytes);
+                });
+            } else if let Some(prefix) = attrs.byte_len_prefix {
+                let bytes = format_ident!("___instructor_encoded_{}", i);
+                let message = format!("`{}` is too large for its length prefix", ident);
+                statements.push(quote! {
+                    let len = <#prefix as core::convert::TryFrom<usize>>::try_from(#bytes.len()).unwrap_or_else(|_| panic!(#message));
+                    instructor::Instruct::<#field_endian>::write_to_buffer(&len, buffer);
+                    instructor::BufferMut::extend_from_slice(buffer, &#bytes);
+                });
+            } else if let Some(width) = attrs.bit_width {
+                let align = attrs.byte_align.then(|| quote! { instructor::BitWriter::align(buffer); });
+                statements.push(match attrs.condition {
+                    Some(_) => quote! {
+                        if let Some(value) = #value {
+                            #align
+                            instructor::InstructBits::write_to_bits(value, buffer, #width);
+                        }
+                    },
+                    None => quote! {
+                        #align
+                        instructor::InstructBits::write_to_bits(#value, buffer, #width);
+                    }
+                });
+            } else if let (Some(_), Some(write_with)) = (&attrs.condition, &attrs.write_with) {
+                // custom functions only handle present values
+                let write = write_value(Some(write_with), field_endian, quote! { value }, quote! { buffer });
+                statements.push(quote! {
+                    if let Some(value) = #value {
+                        #write;
+                    }
+                });
+            } else {
+                let write = write_value(attrs.write_with.as_ref(), field_endian, value, quote! { buffer });
+                statements.push(quote! {
+                    #write;
+                });
+            }
+            if let Some(n) = attrs.pad_after {
+                statements.push(quote! {
+                    instructor::BufferMut::pad(buffer, #n, #fill);
+                });
+            }
         }
     }
-    if let Some(bitfield) = bitfield_ident.take() {
+    if let Some((bitfield, endian)) = bitfield_ident.take() {
         statements.push(quote! {
             instructor::Instruct::<#endian>::write_to_buffer(&#bitfield, buffer);
         });
     }
-    let generic = match endian {
-        Endian::Generic => quote! { <E: instructor::Endian> },
-        _ => quote! {}
+    if bit_stream {
+        statements.push(finish_bit_stream());
+    }
+    statements.push(checksum_boundary(&checksums, data.fields.len(), Some(&field_endians)));
+    if let Some(align) = top.align {
+        statements.push(quote! {
+            let ___instructor_padding = (#align - buffer.written() % #align) % #align;
+            instructor::BufferMut::pad(buffer, ___instructor_padding, #fill);
+        });
+    }
+    if let Some(validate) = top.validate {
+        validations.push(quote! {
+            #validate(self).map_err(Into::<instructor::Error>::into)?;
+        });
+    }
+    let validate = match validations.is_empty() {
+        true => quote! {},
+        false => quote! {
+            #[inline]
+            fn validate(&self) -> core::result::Result<(), instructor::Error> {
+                #(#validations)*
+                Ok(())
+            }
+        }
     };
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
     let output = quote! {
         #[automatically_derived]
-        impl #generic instructor::Instruct<#endian> for #ident {
+        impl #impl_generics instructor::Instruct<#endian> for #ident #ty_generics #where_clause {
             #[inline]
             fn write_to_buffer<B: instructor::BufferMut>(&self, buffer: &mut B) {
+                #(#checks)*
+                #(#encoded)*
                 #(#statements)*
             }
+
+            #validate
         }
     };
     Ok(output)
 }
 
-fn generate_int_enum_impl(endian: Endian, repr: Ident, ident: Ident, data: DataEnum) -> syn::Result<TokenStream> {
-    for variant in data.variants.iter() {
-        if variant.discriminant.is_none() {
-            return Err(syn::Error::new_spanned(&variant.ident, "every variant must have a discriminant"));
+/// Pads the current byte with zero bits and restores the `buffer` shadowed by the bit writer.
+fn finish_bit_stream() -> TokenStream {
+    quote! {
+        #[allow(unused_variables)]
+        let buffer = ___instructor_bits.finish();
+    }
+}
+
+/// Converts a value back into the wire type given by `map` or `try_map`, which is always infallible.
+fn map_value(attrs: &FieldAttributes, value: TokenStream) -> TokenStream {
+    match attrs.map.as_ref().or(attrs.try_map.as_ref()) {
+        Some(wire) if attrs.condition.is_some() => quote! {
+            &core::option::Option::map(core::clone::Clone::clone(#value), core::convert::Into::<#wire>::into)
+        },
+        Some(wire) => quote! { &core::convert::Into::<#wire>::into(core::clone::Clone::clone(#value)) },
+        None => value
+    }
+}
+
+/// Writes a single value, either through its `Instruct` implementation or a custom function.
+fn write_value(write_with: Option<&Path>, endian: impl ToTokens, value: TokenStream, buffer: TokenStream) -> TokenStream {
+    match write_with {
+        Some(write_with) => quote! { #write_with::<#endian, _>(#value, #buffer) },
+        None => quote! { instructor::Instruct::<#endian>::write_to_buffer(#value, #buffer) }
+    }
+}
+
+/// Collects the named fields an expression refers to, skipping names that follow `.` or `::`.
+fn referenced_fields(tokens: TokenStream, fields: &Fields, found: &mut Vec<Ident>) {
+    let mut qualified = false;
+    for token in tokens {
+        let follows = qualified;
+        qualified = matches!(&token, TokenTree::Punct(punct) if matches!(punct.as_char(), '.' | ':'));
+        match token {
+            TokenTree::Group(group) => referenced_fields(group.stream(), fields, found),
+            TokenTree::Ident(ident) if !follows && !found.contains(&ident) && fields.iter().any(|field| field.ident.as_ref() == Some(&ident)) => {
+                found.push(ident)
+            }
+            _ => {}
         }
     }
-    let generic = match endian {
-        Endian::Generic => quote! { <E: instructor::Endian> },
-        _ => quote! {}
+}
+
+fn member(index: usize, field: &Field) -> TokenStream {
+    field
+        .ident
+        .as_ref()
+        .map(|i| i.to_token_stream())
+        .unwrap_or_else(|| Index::from(index).to_token_stream())
+}
+
+fn generate_int_enum_impl(endian: Endian, generics: ImplGenerics, repr: Ident, ident: Ident, data: DataEnum) -> syn::Result<TokenStream> {
+    let variants = int_variants(&data)?;
+    // values outside of their range are rejected before writing, so that `try_write` reports them
+    let checks = variants
+        .iter()
+        .filter_map(|variant| {
+            let ident = &variant.ident;
+            let range = variant.range.as_ref()?;
+            Some(quote! {
+                Self::#ident(value) if !(#range).contains(value) => core::result::Result::Err(instructor::Error::invalid_value(*value))
+            })
+        })
+        .collect::<Vec<_>>();
+    let validate = match checks.is_empty() {
+        true => quote! {},
+        false => quote! {
+            #[inline]
+            fn validate(&self) -> core::result::Result<(), instructor::Error> {
+                match self {
+                    #(#checks,)*
+                    _ => core::result::Result::Ok(())
+                }
+            }
+        }
     };
+    let to_repr = to_repr(&ident, &variants, quote! { self }, true);
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
     let output = quote! {
         #[automatically_derived]
-        impl #generic instructor::Instruct<#endian> for #ident {
+        impl #impl_generics instructor::Instruct<#endian> for #ident #ty_generics #where_clause {
             #[inline]
             fn write_to_buffer<B: instructor::BufferMut>(&self, buffer: &mut B) {
-                let discriminant: #repr = unsafe { core::mem::transmute_copy(self) };
+                let discriminant: #repr = #to_repr;
                 instructor::Instruct::<#endian>::write_to_buffer(&discriminant, buffer)
             }
+
+            #validate
         }
     };
     Ok(output)
 }
 
-fn generate_data_enum_impl(endian: Endian, ident: Ident, data: DataEnum) -> syn::Result<TokenStream> {
+/// Matches a reference to the int-like enum, evaluating to the value it is written as.
+/// Unless `checked`, a range variant evaluates to the value it holds even if it is outside of its range.
+pub(crate) fn to_repr(enum_ident: &Ident, variants: &[IntVariant], value: TokenStream, checked: bool) -> TokenStream {
+    let variants = variants.iter().map(|variant| {
+        let ident = variant.ident;
+        match (variant.values.first(), &variant.range) {
+            (Some(value), _) => quote! { #enum_ident::#ident { .. } => #value },
+            (None, Some(range)) if checked => {
+                // validation is the place to reject values, this only guards against writing corrupted data
+                let message = format!("`{}` holds a value outside of its range", ident);
+                quote! {
+                    #enum_ident::#ident(value) => {
+                        assert!((#range).contains(value), #message);
+                        *value
+                    }
+                }
+            }
+            (None, _) => quote! { #enum_ident::#ident(value) => *value }
+        }
+    });
+    quote! {
+        match #value {
+            #(#variants),*
+        }
+    }
+}
+
+fn generate_data_enum_impl(endian: Endian, generics: ImplGenerics, tag: Option<Type>, ident: Ident, data: DataEnum) -> syn::Result<TokenStream> {
+    let type_name = ident.to_string();
     let mut matches = Vec::new();
+    let mut validations = Vec::new();
+    let mut tags = Vec::new();
+    let mut default = None;
     for variant in data.variants.iter() {
         if variant.discriminant.is_some() {
             return Err(syn::Error::new_spanned(&variant.ident, "disciminants are not supported for data enums"));
         }
         let ident = &variant.ident;
+        let attrs = parse_variant_attributes(&variant.attrs)?;
+        if attrs.unknown {
+            return Err(syn::Error::new_spanned(ident, "`unknown` is only supported for int-like enums"));
+        }
+        if tag.is_some() {
+            match attrs.tag_value {
+                Some(tag_value) if attrs.default => default = Some((ident, tag_value)),
+                Some(tag_value) => tags.push((ident, tag_value)),
+                None => return Err(syn::Error::new_spanned(ident, "every variant must have a tag_value"))
+            }
+        }
         let fields = &variant
             .fields
             .iter()
             .enumerate()
             .map(|(i, f)| f.ident.clone().unwrap_or_else(|| format_ident!("arg{}", i)))
             .collect::<Vec<_>>();
+        let names = variant
+            .fields
+            .iter()
+            .enumerate()
+            .map(|(i, f)| f.ident.as_ref().map_or_else(|| i.to_string(), Ident::to_string));
+        let validate = quote! {
+            #(instructor::Instruct::<#endian>::validate(#fields).map_err(|e| e.in_field(#type_name, #names))?;)*
+        };
 
         matches.push(match &variant.fields {
             Fields::Named(_) => quote! {
@@ -152,19 +602,89 @@ fn generate_data_enum_impl(endian: Endian, ident: Ident, data: DataEnum) -> syn:
             },
             Fields::Unit => quote! { Self::#ident => {} }
         });
+        validations.push(match &variant.fields {
+            Fields::Named(_) => quote! { Self::#ident { #(#fields),* } => { #validate } },
+            Fields::Unnamed(_) => quote! { Self::#ident(#(#fields),*) => { #validate } },
+            Fields::Unit => quote! { Self::#ident => {} }
+        });
     }
-    let generic = match endian {
-        Endian::Generic => quote! { <E: instructor::Endian> },
-        _ => quote! {}
+    let validate = quote! {
+        #[inline]
+        fn validate(&self) -> core::result::Result<(), instructor::Error> {
+            match self {
+                #(#validations)*
+            }
+            Ok(())
+        }
+    };
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
+    let Some(tag) = tag else {
+        let output = quote! {
+            #[automatically_derived]
+            impl #impl_generics instructor::Instruct<#endian> for #ident #ty_generics #where_clause {
+                #[inline]
+                fn write_to_buffer<B: instructor::BufferMut>(&self, buffer: &mut B) {
+                    match self {
+                        #(#matches)*
+                    }
+                }
+
+                #validate
+            }
+        };
+        return Ok(output);
     };
+    let mut write_tags = tags
+        .iter()
+        .map(|(ident, tag_value)| quote! { Self::#ident { .. } => instructor::BufferMut::write::<#tag, #endian>(buffer, #tag_value) })
+        .collect::<Vec<_>>();
+    let mut matches_tags = tags
+        .iter()
+        .map(|(ident, tag_value)| quote! { Self::#ident { .. } => matches!(*tag, #tag_value) })
+        .collect::<Vec<_>>();
+    if let Some((ident, tag_value)) = default {
+        // the default variant is also read back for every tag not claimed by another variant
+        let others = tags.iter().map(|(_, tag_value)| tag_value);
+        let unclaimed = match tags.is_empty() {
+            true => quote! { true },
+            false => quote! { !matches!(*tag, #(#others)|*) }
+        };
+        write_tags.push(quote! { Self::#ident { .. } => instructor::BufferMut::write::<#tag, #endian>(buffer, #tag_value) });
+        matches_tags.push(quote! { Self::#ident { .. } => matches!(*tag, #tag_value) || #unclaimed });
+    }
     let output = quote! {
         #[automatically_derived]
-        impl #generic instructor::Instruct<#endian> for #ident {
+        impl #impl_generics instructor::InstructTagged<#endian> for #ident #ty_generics #where_clause {
+            type Tag = #tag;
+
+            fn matches_tag(&self, tag: &Self::Tag) -> bool {
+                match self {
+                    #(#matches_tags,)*
+                }
+            }
+
+            fn write_untagged_to_buffer<B: instructor::BufferMut>(&self, buffer: &mut B) {
+                match self {
+                    #(#matches)*
+                }
+            }
+
+            #validate
+        }
+
+        #[automatically_derived]
+        impl #impl_generics instructor::Instruct<#endian> for #ident #ty_generics #where_clause {
             #[inline]
             fn write_to_buffer<B: instructor::BufferMut>(&self, buffer: &mut B) {
                 match self {
-                    #(#matches)*
+                    #(#write_tags,)*
                 }
+                instructor::InstructTagged::<#endian>::write_untagged_to_buffer(self, buffer);
+            }
+
+            #[inline]
+            fn validate(&self) -> core::result::Result<(), instructor::Error> {
+                instructor::InstructTagged::<#endian>::validate(self)
             }
         }
     };
@@ -254,4 +774,457 @@ mod tests {
         let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
         print!("{}", formatted);
     }
+
+    #[test]
+    fn print_tagged_enum() {
+        let input = syn::parse_quote! {
+            #[instructor(tag = u8)]
+            enum Data {
+                #[instructor(tag_value = 0x01)]
+                A {
+                    a: u8,
+                    b: u16
+                },
+                #[instructor(tag_value = 0x02)]
+                B(i32),
+                #[instructor(default, tag_value = 0xFF)]
+                C
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_select() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Signaling {
+                code: SignalingCodes,
+                id: u8,
+                #[instructor(select = "code")]
+                body: SignalingBody
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_generics() {
+        let input = syn::parse_quote! {
+            struct Frame<'a, T: Copy, U, const N: usize>
+            where
+                U: Default
+            {
+                header: u16,
+                body: T,
+                trailer: [u8; N],
+                marker: PhantomData<&'a U>
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_generics_bound() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big", bound = "T: Copy")]
+            struct Header<T> {
+                length: u16,
+                marker: PhantomData<T>
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_field_endian() {
+        let input = syn::parse_quote! {
+            struct Header {
+                magic: u32,
+                #[instructor(endian = "big")]
+                length: u16,
+                #[instructor(bitfield(u16), endian = "little")]
+                #[instructor(bits(0..4))]
+                a: u8,
+                #[instructor(bits(4..16))]
+                b: u16
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_counted() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Table {
+                num_entries: u8,
+                #[instructor(count = "num_entries")]
+                entries: Vec<u16>,
+                #[instructor(count_prefix = u16)]
+                names: Vec<[u8; 4]>
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_byte_len() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Message {
+                len: u8,
+                #[instructor(byte_len = "len")]
+                body: Body,
+                #[instructor(byte_len_prefix = u16)]
+                extension: Extension,
+                trailer: u8
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_constants() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big", magic = b"\x89PNG")]
+            struct Signature {
+                #[instructor(const = 0x0d0a_1a0a)]
+                line_endings: u32,
+                version: u8
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_padding() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little", align = 8, pad_byte = 0xff, strict_padding)]
+            struct Record {
+                kind: u8,
+                #[instructor(pad_before = 3)]
+                len: u32,
+                #[instructor(pad_after = 2)]
+                flags: u16
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_conditional() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Event {
+                version: u8,
+                flags: Flags,
+                #[instructor(if = "flags.contains(Flags::EXT)")]
+                extended: Option<u32>,
+                #[instructor(if = "version >= 2")]
+                timestamp: Option<u64>
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_calc() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Command {
+                code: u8,
+                #[instructor(calc = "data.len() as u16 + 1", verify_calc)]
+                length: u16,
+                id: u8,
+                data: Vec<u8>
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_checksum() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct Frame {
+                kind: u8,
+                len: u16,
+                #[instructor(checksum = instructor::checksum::Xor)]
+                header_checksum: u8,
+                payload: [u8; 4],
+                #[instructor(checksum = instructor::checksum::Crc32, covers = "kind..=payload")]
+                crc: u32
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_validate() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little", validate = "validate_range")]
+            struct Range {
+                #[instructor(validate = "validate_port")]
+                start: u16,
+                end: u16
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_with() {
+        let input = syn::parse_quote! {
+            struct Measurement {
+                #[instructor(with = "scaled")]
+                temperature: f32,
+                #[instructor(read_with = "timestamp::read", write_with = "timestamp::write")]
+                time: SystemTime,
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(0..4), with = "nibble")]
+                unit: Unit,
+                #[instructor(bits(4..8))]
+                channel: u8
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_skip() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Packet {
+                id: u16,
+                #[instructor(skip)]
+                cache: Vec<u8>,
+                #[instructor(skip, default = "Instant::now()")]
+                received: Instant,
+                len: u8
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_map() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct Reading {
+                #[instructor(map = "u16")]
+                voltage: Millivolts,
+                #[instructor(try_map = "u8")]
+                channel: Channel
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_msb0() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big", bit_order = "msb0")]
+            struct Ipv4Header {
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(0..4))]
+                version: u8,
+                #[instructor(bits(4..8))]
+                ihl: u8,
+                #[instructor(bitfield(u16, lsb0))]
+                #[instructor(bits(0..13))]
+                fragment_offset: u16,
+                #[instructor(bits(13..16))]
+                flags: u8
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_signed_bits() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Motion {
+                #[instructor(bitfield(u16))]
+                #[instructor(bits(0..6))]
+                dx: i8,
+                #[instructor(bits(6..12))]
+                dy: i8,
+                #[instructor(bits(12..13))]
+                pressed: bool,
+                #[instructor(bits(13..16), signed)]
+                wheel: Wheel
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_exhaustive_bitfield() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct Control {
+                #[instructor(bitfield(u8, msb0, exhaustive))]
+                #[instructor(bits(0..1))]
+                poll: bool,
+                #[instructor(bits(1..8))]
+                sequence: u8,
+                #[instructor(bitfield(u24, exhaustive))]
+                #[instructor(bits(0..24))]
+                offset: u32
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_bit_stream() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct SliceHeader {
+                #[instructor(bits = 1)]
+                first_slice: bool,
+                #[instructor(bits = 5)]
+                slice_type: u8,
+                #[instructor(bits = 7)]
+                qp_delta: i8,
+                #[instructor(bits = 1, if = "slice_type == 2")]
+                long_term: Option<bool>,
+                #[instructor(bits = 8, byte_align)]
+                frame_num: u8,
+                length: u16
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_unknown_enum() {
+        let input = syn::parse_quote! {
+            #[repr(u8)]
+            enum Opcode {
+                Read = 0x01,
+                Write = 0x02,
+                #[instructor(unknown)]
+                Unknown(u8)
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_enum_values() {
+        let input = syn::parse_quote! {
+            #[repr(u8)]
+            enum Command {
+                Reset,
+                Status,
+                Read = 0x10,
+                Write,
+                #[instructor(values(0x20, 0x21))]
+                Erase,
+                #[instructor(value = ESCAPE)]
+                Escape
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_enum_ranges() {
+        let input = syn::parse_quote! {
+            #[repr(u8)]
+            enum Code {
+                Ping = 0x01,
+                Pong = 0x02,
+                #[instructor(range = 0x20..RESERVED_END)]
+                Reserved(u8),
+                #[instructor(range = 0xF0..=0xFF)]
+                Vendor(u8)
+            }
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_bitflags_modes() {
+        let input = syn::parse_quote! {
+            #[instructor(bitflags(truncate))]
+            struct Features(u8);
+        };
+
+        let output = derive_pack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
 }
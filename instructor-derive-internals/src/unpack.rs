@@ -1,82 +1,293 @@
 use proc_macro2::{Ident, TokenStream};
-use quote::{format_ident, quote};
-use syn::{Data, DataEnum, DataStruct, DeriveInput, Fields};
+use quote::{format_ident, quote, ToTokens};
+use syn::{Data, DataEnum, DataStruct, DeriveInput, Expr, Field, Fields, Member, Path, Type};
 
-use crate::attr::{get_bitfield_start, get_repr, is_default, parse_top_level_attributes, Endian};
+use crate::attr::{
+    check_bit_width, check_bitfields, checksum_boundary, checksum_ident, checksum_ranges, find_field, get_repr, int_literal, int_variants,
+    local_ident, parse_field_attributes, parse_top_level_attributes, parse_variant_attributes, signed_member, BitflagsMode, ChecksumRange, Endian,
+    IntVariant, TopLevelAttributes, VariantRange
+};
+use crate::generics::{impl_generics, ImplGenerics};
 
 pub fn derive_unpack(input: DeriveInput) -> syn::Result<TokenStream> {
-    let DeriveInput { ident, data, attrs, .. } = input;
+    let DeriveInput { ident, data, attrs, generics, .. } = input;
 
-    let (endian, bitflags) = parse_top_level_attributes(&attrs)?;
+    let top = parse_top_level_attributes(&attrs)?;
+    let generics = impl_generics(&generics, &top.endian, quote! { instructor::Exstruct }, top.bound.as_ref())?;
 
-    if bitflags {
-        return generate_bitflags_impl(endian, ident);
+    if let Some(mode) = top.bitflags {
+        return generate_bitflags_impl(top.endian, generics, mode, ident);
     }
     match data {
-        Data::Struct(data) => generate_struct_impl(endian, ident, data),
-        Data::Enum(data) => match get_repr(&attrs)? {
-            Some(repr) => generate_enum_impl(endian, repr, ident, data),
-            None => Err(syn::Error::new_spanned(ident, "enums must have a repr attribute"))
+        Data::Struct(data) => generate_struct_impl(top, generics, ident, data),
+        Data::Enum(data) => match (top.tag, get_repr(&attrs)?) {
+            (Some(tag), _) => generate_data_enum_impl(top.endian, generics, tag, ident, data),
+            (None, Some(repr)) => generate_enum_impl(top.endian, generics, repr, ident, data),
+            (None, None) => Err(syn::Error::new_spanned(ident, "enums must have either a repr or a tag attribute"))
         },
         Data::Union(_) => Err(syn::Error::new_spanned(ident, "unions are not supported"))
     }
 }
 
-fn generate_bitflags_impl(endian: Endian, ident: Ident) -> syn::Result<TokenStream> {
-    let generic = match endian {
-        Endian::Generic => quote! { <E: instructor::Endian> },
-        _ => quote! {}
+fn generate_bitflags_impl(endian: Endian, generics: ImplGenerics, mode: BitflagsMode, ident: Ident) -> syn::Result<TokenStream> {
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
+    let flags = match mode {
+        BitflagsMode::Strict => quote! { Self::from_bits(bits).ok_or_else(|| instructor::Error::invalid_value(bits)) },
+        BitflagsMode::Truncate => quote! { Ok(Self::from_bits_truncate(bits)) },
+        BitflagsMode::Retain => quote! { Ok(Self::from_bits_retain(bits)) }
     };
     let output = quote! {
         #[automatically_derived]
-        impl #generic instructor::Exstruct<#endian> for #ident {
+        impl #impl_generics instructor::Exstruct<#endian> for #ident #ty_generics #where_clause {
             #[inline]
             fn read_from_buffer<B: instructor::Buffer>(buffer: &mut B) -> Result<Self, instructor::Error> {
-                Self::from_bits(instructor::Exstruct::<#endian>::read_from_buffer(buffer)?)
-                    .ok_or(instructor::Error::InvalidValue)
+                let bits = instructor::Exstruct::<#endian>::read_from_buffer(buffer)?;
+                #flags
             }
         }
     };
     Ok(output)
 }
 
-fn generate_struct_impl(endian: Endian, ident: Ident, data: DataStruct) -> syn::Result<TokenStream> {
+fn generate_struct_impl(top: TopLevelAttributes, generics: ImplGenerics, ident: Ident, data: DataStruct) -> syn::Result<TokenStream> {
+    let endian = top.endian;
+    let type_name = ident.to_string();
     let mut bitfield_ident = None;
+    let mut bitfield_offset: Option<Ident> = None;
+    let mut bit_order = top.bit_order;
+    let mut bit_stream = false;
     let mut fields = Vec::new();
     let mut statements = Vec::new();
-    for field in data.fields.iter() {
-        let ident = field
-            .ident
-            .clone()
-            .unwrap_or_else(|| format_ident!("field_{}", fields.len()));
+    // computed fields are verified once every field they may depend on has been read
+    let mut checks = Vec::new();
+    if !data.fields.is_empty() || top.magic.is_some() || top.align.is_some() {
+        statements.push(quote! {
+            let ___instructor_start = instructor::Buffer::remaining(buffer);
+        });
+    }
+    if let Some(magic) = &top.magic {
+        let len = magic.value().len();
+        let context = field_context(&type_name, "magic", quote! { 0 });
+        let error = error_context(&type_name, "magic", quote! { 0 });
+        statements.push(quote! {
+            let ___instructor_magic: [u8; #len] = instructor::Exstruct::<#endian>::read_from_buffer(buffer)#context?;
+            if ___instructor_magic != *#magic {
+                return Err(instructor::Error::new(instructor::ErrorKind::ConstantMismatch)#error);
+            }
+        });
+    }
+    let attributes = data
+        .fields
+        .iter()
+        .map(|field| parse_field_attributes(&field.attrs))
+        .collect::<syn::Result<Vec<_>>>()?;
+    check_bitfields(&data.fields, &attributes)?;
+    let checksums = checksum_ranges(&data.fields, &attributes)?;
+    // checksums covering their own field can only be verified once their range has been read
+    let mut pending = vec![None; attributes.len()];
+    for (field, attrs) in data.fields.iter().zip(attributes) {
+        let ident = local_ident(fields.len(), field);
         let ty = &field.ty;
-        let (bitfield, bitrange) = get_bitfield_start(&field.attrs)?;
-        if let Some(bitfield) = bitfield {
+        let name = field_name(fields.len(), field);
+        // members of a bitfield begin where the bitfield does
+        let offset = match (&attrs.bitfield, &bitfield_offset) {
+            (None, Some(bitfield_offset)) if attrs.bits.is_some() => bitfield_offset.clone(),
+            _ => format_ident!("___instructor_offset_{}", fields.len())
+        };
+        let context = field_context(&type_name, &name, offset.to_token_stream());
+        let endian = attrs.endian.unwrap_or(endian);
+        check_bit_width(field, &attrs)?;
+        let signed = signed_member(field, &attrs)?;
+        if attrs.bits.is_some() && (attrs.pad_before.is_some() || attrs.pad_after.is_some()) {
+            return Err(syn::Error::new_spanned(field, "bitfield members cannot be padded"));
+        }
+        if attrs.bit_width.is_none() && bit_stream {
+            statements.push(finish_bit_stream());
+            bit_stream = false;
+        }
+        statements.push(checksum_boundary(&checksums, fields.len(), None));
+        statements.extend(covered_checks(&checksums, fields.len(), &mut pending));
+        if attrs.bit_width.is_some() && !bit_stream {
+            // consecutive fields with a bit width share a reader that shadows `buffer`
+            statements.push(quote! {
+                let mut ___instructor_bits = instructor::BitReader::new(buffer);
+                let buffer = &mut ___instructor_bits;
+            });
+            bit_stream = true;
+        }
+        if attrs.bitfield.is_some() || attrs.bits.is_none() {
+            statements.push(field_offset(&offset));
+        }
+        if let Some(n) = attrs.pad_before {
+            statements.push(skip_padding(&top, quote! { #n }, &context));
+            statements.push(field_offset(&offset));
+        }
+        if let Some(bitfield) = attrs.bitfield {
             let ident = quote! { ___instructor_bitfield };
             statements.push(quote! {
-                let mut #ident = instructor::BitBuffer::<#bitfield>::new::<#endian, B>(buffer)?;
+                let mut #ident = instructor::BitBuffer::<#bitfield>::new::<#endian, _>(buffer)#context?;
             });
             bitfield_ident = Some(ident);
+            bitfield_offset = Some(offset.clone());
+            bit_order = attrs.bit_order.unwrap_or(top.bit_order);
         }
-        if let Some((start, end)) = bitrange {
+        if let Some((start, end)) = attrs.bits {
             match bitfield_ident.as_ref() {
                 Some(bitfield_ident) => {
+                    let set_range = bit_order.set_range();
+                    let signed = signed.then(|| quote! { #bitfield_ident.set_signed(); });
+                    // members are big endian numbers, unless their type only supports another byte order
+                    let member_endian = attrs.endian.map_or_else(|| quote! { instructor::BigEndian }, |endian| quote! { #endian });
+                    let reorder = attrs.endian.map(|endian| quote! { #bitfield_ident.set_endian::<#endian>(); });
+                    let read = read_value(attrs.read_with.as_ref(), member_endian, quote! { &mut #bitfield_ident });
+                    let read = map_value(&attrs.map, &attrs.try_map, quote! { #read #context? }, &context);
                     statements.push(quote! {
-                        #bitfield_ident.set_range(#start, #end);
-                        let #ident: #ty = instructor::Exstruct::<instructor::BigEndian>::read_from_buffer(&mut #bitfield_ident)?;
+                        #bitfield_ident.#set_range(#start, #end);
+                        #signed
+                        #reorder
+                        let #ident: #ty = #read;
                     });
                 }
                 None => return Err(syn::Error::new_spanned(field, "bitfield range without bitfield"))
             }
         } else {
             bitfield_ident = None;
+            bitfield_offset = None;
+            let read = if attrs.skip {
+                match attrs.default {
+                    Some(default) => quote! { #default },
+                    None => quote! { core::default::Default::default() }
+                }
+            } else if let Some(select) = attrs.select {
+                let tag = preceding_local(&data.fields, fields.len(), &select)?;
+                quote! { instructor::ExstructTagged::<#endian>::read_tagged_from_buffer(&#tag, buffer)#context? }
+            } else if let Some(count) = attrs.count {
+                let count = preceding_local(&data.fields, fields.len(), &count)?;
+                quote! {{
+                    let count = usize::try_from(#count).map_err(|_| instructor::Error::new(instructor::ErrorKind::InvalidValue))#context?;
+                    instructor::Buffer::read_vec::<_, #endian>(buffer, count)#context?
+                }}
+            } else if let Some(prefix) = attrs.count_prefix {
+                quote! {{
+                    let count: #prefix = instructor::Exstruct::<#endian>::read_from_buffer(buffer)#context?;
+                    let count = usize::try_from(count).map_err(|_| instructor::Error::new(instructor::ErrorKind::InvalidValue))#context?;
+                    instructor::Buffer::read_vec::<_, #endian>(buffer, count)#context?
+                }}
+            } else if let Some(len) = attrs.byte_len {
+                let len = preceding_local(&data.fields, fields.len(), &len)?;
+                quote! {{
+                    let len = usize::try_from(#len).map_err(|_| instructor::Error::new(instructor::ErrorKind::InvalidValue))#context?;
+                    instructor::Buffer::read_limited::<_, #endian>(buffer, len)#context?
+                }}
+            } else if let Some(prefix) = attrs.byte_len_prefix {
+                quote! {{
+                    let len: #prefix = instructor::Exstruct::<#endian>::read_from_buffer(buffer)#context?;
+                    let len = usize::try_from(len).map_err(|_| instructor::Error::new(instructor::ErrorKind::InvalidValue))#context?;
+                    instructor::Buffer::read_limited::<_, #endian>(buffer, len)#context?
+                }}
+            } else if let Some(width) = attrs.bit_width {
+                let read = quote! { instructor::ExstructBits::read_from_bits(buffer, #width)#context? };
+                match attrs.byte_align {
+                    true => quote! {{
+                        instructor::BitReader::align(buffer);
+                        #read
+                    }},
+                    false => read
+                }
+            } else {
+                let read = read_value(attrs.read_with.as_ref(), endian, quote! { buffer });
+                quote! { #read #context? }
+            };
+            let read = map_value(&attrs.map, &attrs.try_map, read, &context);
+            let read = match attrs.condition {
+                Some(condition) => quote! {
+                    if #condition {
+                        Some(#read)
+                    } else {
+                        None
+                    }
+                },
+                None => read
+            };
+            let read = match checksums.iter().find(|range| range.field == fields.len() && range.covers_itself()) {
+                // the checksum is computed as if the field was zero
+                Some(_) => quote! {
+                    instructor::checksum::Digest::read_zeroed(buffer, |buffer| {
+                        let value: #ty = #read;
+                        Ok(value)
+                    })?
+                },
+                None => read
+            };
+            statements.push(quote! {
+                let #ident: #ty = #read;
+            });
+        }
+        if let Some(constant) = attrs.constant {
+            let error = error_context(&type_name, &name, offset.to_token_stream());
+            statements.push(quote! {
+                if #ident != #constant {
+                    return Err(instructor::Error::new(instructor::ErrorKind::ConstantMismatch)#error);
+                }
+            });
+        }
+        if attrs.checksum.is_some() {
+            let checksum = checksum_ident(fields.len());
+            let error = error_context(&type_name, &name, offset.to_token_stream());
+            let check = quote! {
+                if #ident != #checksum {
+                    return Err(instructor::Error::new(instructor::ErrorKind::ChecksumMismatch)#error);
+                }
+            };
+            match checksums.iter().any(|range| range.field == fields.len() && range.covers_itself()) {
+                true => pending[fields.len()] = Some(check),
+                false => statements.push(check)
+            }
+        }
+        if let Some(validate) = attrs.validate {
+            let error = error_context(&type_name, &name, offset.to_token_stream());
             statements.push(quote! {
-                let #ident: #ty = instructor::Exstruct::<#endian>::read_from_buffer(buffer)?;
+                #validate(&#ident).map_err(|e| Into::<instructor::Error>::into(e)#error)?;
             });
         }
+        if let Some(n) = attrs.pad_after {
+            statements.push(skip_padding(&top, quote! { #n }, &context));
+        }
+
+        if let (Some(calc), true) = (attrs.calc, attrs.verify_calc) {
+            checks.push((ident.clone(), ty, calc, error_context(&type_name, &name, offset.to_token_stream())));
+        }
 
         fields.push(ident);
     }
+    if bit_stream {
+        statements.push(finish_bit_stream());
+    }
+    statements.push(checksum_boundary(&checksums, fields.len(), None));
+    statements.extend(covered_checks(&checksums, fields.len(), &mut pending));
+    for (ident, ty, calc, error) in checks {
+        statements.push(quote! {
+            {
+                #(
+                    #[allow(unused_variables)]
+                    let #fields = &#fields;
+                )*
+                let expected: #ty = #calc;
+                if *#ident != expected {
+                    return Err(instructor::Error::new(instructor::ErrorKind::InvalidValue)#error);
+                }
+            }
+        });
+    }
+    if let Some(align) = top.align {
+        let context = field_context(&type_name, "padding", quote! { ___instructor_offset });
+        let skip = skip_padding(&top, quote! { ___instructor_padding }, &context);
+        statements.push(quote! {
+            let ___instructor_offset = ___instructor_start - instructor::Buffer::remaining(buffer);
+            let ___instructor_padding = (#align - ___instructor_offset % #align) % #align;
+            #skip
+        });
+    }
     let ret = match data.fields {
         Fields::Named(_) => quote! {
             Self {
@@ -90,13 +301,18 @@ fn generate_struct_impl(endian: Endian, ident: Ident, data: DataStruct) -> syn::
             Self
         }
     };
-    let generic = match endian {
-        Endian::Generic => quote! { <E: instructor::Endian> },
-        _ => quote! {}
+    let ret = match top.validate {
+        Some(validate) => quote! {{
+            let value = #ret;
+            #validate(&value).map_err(Into::<instructor::Error>::into)?;
+            value
+        }},
+        None => ret
     };
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
     let output = quote! {
         #[automatically_derived]
-        impl #generic instructor::Exstruct<#endian> for #ident {
+        impl #impl_generics instructor::Exstruct<#endian> for #ident #ty_generics #where_clause {
             #[inline]
             fn read_from_buffer<B: instructor::Buffer>(buffer: &mut B) -> core::result::Result<Self, instructor::Error> {
                 #(#statements)*
@@ -107,44 +323,234 @@ fn generate_struct_impl(endian: Endian, ident: Ident, data: DataStruct) -> syn::
     Ok(output)
 }
 
-fn generate_enum_impl(endian: Endian, repr: Ident, ident: Ident, data: DataEnum) -> syn::Result<TokenStream> {
+/// Turns a value of an int-like enum into a pattern.
+/// Values other than literals are bound to constants so that they can be matched against.
+fn value_pattern(value: &Expr, repr: &Ident, constants: &mut Vec<TokenStream>) -> TokenStream {
+    match int_literal(value) {
+        Some(_) => value.to_token_stream(),
+        None => {
+            let constant = format_ident!("___INSTRUCTOR_VALUE_{}", constants.len());
+            constants.push(quote! { const #constant: #repr = #value; });
+            constant.to_token_stream()
+        }
+    }
+}
+
+/// Verifies the checksums covering their own field whose ranges end right before field `index`.
+fn covered_checks(checksums: &[ChecksumRange], index: usize, pending: &mut [Option<TokenStream>]) -> Vec<TokenStream> {
+    checksums
+        .iter()
+        .filter(|range| range.covers_itself() && range.last + 1 == index)
+        .filter_map(|range| pending[range.field].take())
+        .collect()
+}
+
+/// Drops the bits left in the current byte and restores the `buffer` shadowed by the bit reader.
+fn finish_bit_stream() -> TokenStream {
+    quote! {
+        #[allow(unused_variables)]
+        let buffer = ___instructor_bits.finish();
+    }
+}
+
+/// Reads a single value, either through its `Exstruct` implementation or a custom function.
+fn read_value(read_with: Option<&Path>, endian: impl ToTokens, buffer: TokenStream) -> TokenStream {
+    match read_with {
+        Some(read_with) => quote! { #read_with::<#endian, _>(#buffer) },
+        None => quote! { instructor::Exstruct::<#endian>::read_from_buffer(#buffer) }
+    }
+}
+
+/// Converts a value read as the wire type given by `map` or `try_map` into the type of the field.
+fn map_value(map: &Option<Type>, try_map: &Option<Type>, read: TokenStream, context: &TokenStream) -> TokenStream {
+    match (map, try_map) {
+        (Some(wire), _) => quote! {{
+            let raw: #wire = #read;
+            core::convert::Into::into(raw)
+        }},
+        (None, Some(wire)) => quote! {{
+            let raw: #wire = #read;
+            core::convert::TryFrom::try_from(raw).map_err(|_| instructor::Error::new(instructor::ErrorKind::InvalidValue))#context?
+        }},
+        (None, None) => read
+    }
+}
+
+fn field_name(index: usize, field: &Field) -> String {
+    field
+        .ident
+        .as_ref()
+        .map(|i| i.to_string())
+        .unwrap_or_else(|| index.to_string())
+}
+
+/// Records where a field begins, so that errors point at its start rather than where parsing stopped.
+fn field_offset(offset: &Ident) -> TokenStream {
+    quote! {
+        #[allow(unused_variables)]
+        let #offset = ___instructor_start - instructor::Buffer::remaining(buffer);
+    }
+}
+
+fn field_context(type_name: &str, field_name: &str, offset: TokenStream) -> TokenStream {
+    let error = error_context(type_name, field_name, offset);
+    quote! {
+        .map_err(|e| e #error)
+    }
+}
+
+fn error_context(type_name: &str, field_name: &str, offset: TokenStream) -> TokenStream {
+    quote! {
+        .with_field(#type_name, #field_name, #offset, ___instructor_start - instructor::Buffer::remaining(buffer))
+    }
+}
+
+fn skip_padding(top: &TopLevelAttributes, len: TokenStream, context: &TokenStream) -> TokenStream {
+    let fill = top.pad_byte;
+    match top.strict_padding {
+        true => quote! { instructor::Buffer::skip_reserved(buffer, #len, #fill)#context?; },
+        false => quote! { instructor::Buffer::skip(buffer, #len)#context?; }
+    }
+}
+
+fn preceding_local(fields: &Fields, current: usize, member: &Member) -> syn::Result<Ident> {
+    let (index, field) = find_field(fields, member)?;
+    if index >= current {
+        return Err(syn::Error::new_spanned(member, "the referenced field must precede this field"));
+    }
+    Ok(local_ident(index, field))
+}
+
+fn generate_enum_impl(endian: Endian, generics: ImplGenerics, repr: Ident, ident: Ident, data: DataEnum) -> syn::Result<TokenStream> {
+    let from_repr = from_repr(&repr, &int_variants(&data)?);
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
+    let output = quote! {
+        #[automatically_derived]
+        impl #impl_generics instructor::Exstruct<#endian> for #ident #ty_generics #where_clause {
+            #[inline]
+            fn read_from_buffer<B: instructor::Buffer>(buffer: &mut B) -> core::result::Result<Self, instructor::Error> {
+                let value: #repr = instructor::Exstruct::<#endian>::read_from_buffer(buffer)?;
+                #from_repr
+            }
+        }
+    };
+    Ok(output)
+}
+
+/// Converts `value` of the type `repr` into the int-like enum, shared with the derived `TryFrom`.
+pub(crate) fn from_repr(repr: &Ident, int_variants: &[IntVariant]) -> TokenStream {
+    let mut default = None;
+    let mut variants = Vec::new();
+    let mut constants = Vec::new();
+    for variant in int_variants {
+        let ident = variant.ident;
+        if let Some(VariantRange { start, end, inclusive }) = &variant.range {
+            let start = value_pattern(start, repr, &mut constants);
+            let end = value_pattern(end, repr, &mut constants);
+            let range = match inclusive {
+                true => quote! { #start..=#end },
+                false => quote! { #start..#end }
+            };
+            variants.push(quote! {
+                #range => Ok(Self::#ident(value))
+            });
+            continue;
+        }
+        let values = variant.values.iter().map(|value| value_pattern(value, repr, &mut constants)).collect::<Vec<_>>();
+        if variant.unknown {
+            default = Some(quote! { _ => Ok(Self::#ident(value)) });
+            continue;
+        }
+        if variant.default {
+            default = Some(quote! { _ => Ok(Self::#ident) });
+        }
+        variants.push(quote! {
+            #(#values)|* => Ok(Self::#ident)
+        });
+    }
+    let default = default.unwrap_or_else(|| quote! { _ => Err(instructor::Error::invalid_value(value)) });
+    quote! {
+        #(#constants)*
+        match value {
+            #(#variants,)*
+            #default,
+        }
+    }
+}
+
+fn generate_data_enum_impl(endian: Endian, generics: ImplGenerics, tag: Type, ident: Ident, data: DataEnum) -> syn::Result<TokenStream> {
+    let enum_name = ident.to_string();
     let mut default = None;
     let mut variants = Vec::new();
     for variant in data.variants.iter() {
         let ident = &variant.ident;
-        let discr = match &variant.discriminant {
-            Some((_, expr)) => expr,
-            None => return Err(syn::Error::new_spanned(ident, "every variant must have a discriminant"))
+        let attrs = parse_variant_attributes(&variant.attrs)?;
+        if attrs.unknown {
+            return Err(syn::Error::new_spanned(ident, "`unknown` is only supported for int-like enums"));
+        }
+        let tag_value = match attrs.tag_value {
+            Some(tag_value) => tag_value,
+            None => return Err(syn::Error::new_spanned(ident, "every variant must have a tag_value"))
         };
-        if is_default(&variant.attrs)? {
+        let type_name = format!("{}::{}", enum_name, ident);
+        let reads = variant.fields.iter().enumerate().map(|(i, field)| {
+            let offset = format_ident!("___instructor_offset");
+            let start = field_offset(&offset);
+            let context = field_context(&type_name, &field_name(i, field), offset.to_token_stream());
+            quote! {{
+                #start
+                instructor::Exstruct::<#endian>::read_from_buffer(buffer)#context?
+            }}
+        });
+        let body = match &variant.fields {
+            Fields::Named(fields) => {
+                let fields = fields.named.iter().map(|f| &f.ident);
+                quote! { Ok(Self::#ident { #(#fields: #reads),* }) }
+            }
+            Fields::Unnamed(_) => quote! { Ok(Self::#ident(#(#reads),*)) },
+            Fields::Unit => quote! { Ok(Self::#ident) }
+        };
+        if attrs.default {
             if default.is_some() {
                 return Err(syn::Error::new_spanned(ident, "only one variant can be marked as default"));
             }
-            default = Some(ident.clone());
+            default = Some(body.clone());
         }
         variants.push(quote! {
-            #discr => Ok(Self::#ident)
+            #tag_value => #body
         });
     }
-    let generic = match endian {
-        Endian::Generic => quote! { <E: instructor::Endian> },
-        _ => quote! {}
-    };
+    let ImplGenerics { impl_generics, ty_generics, where_clause } = generics;
     let default = match default {
-        Some(ident) => quote! { _ => Ok(Self::#ident) },
-        None => quote! { _ => Err(instructor::Error::InvalidValue) }
+        Some(body) => quote! { _ => #body },
+        None => quote! { _ => Err(instructor::Error::new(instructor::ErrorKind::InvalidValue)) }
+    };
+    let start = match data.variants.iter().any(|v| !v.fields.is_empty()) {
+        true => quote! { let ___instructor_start = instructor::Buffer::remaining(buffer); },
+        false => quote! {}
     };
     let output = quote! {
         #[automatically_derived]
-        impl #generic instructor::Exstruct<#endian> for #ident {
-            fn read_from_buffer<B: instructor::Buffer>(buffer: &mut B) -> core::result::Result<Self, instructor::Error> {
-                let value: #repr = instructor::Exstruct::<#endian>::read_from_buffer(buffer)?;
-                match value {
+        impl #impl_generics instructor::ExstructTagged<#endian> for #ident #ty_generics #where_clause {
+            type Tag = #tag;
+
+            fn read_tagged_from_buffer<B: instructor::Buffer>(tag: &Self::Tag, buffer: &mut B) -> core::result::Result<Self, instructor::Error> {
+                #start
+                match *tag {
                     #(#variants,)*
                     #default,
                 }
             }
         }
+
+        #[automatically_derived]
+        impl #impl_generics instructor::Exstruct<#endian> for #ident #ty_generics #where_clause {
+            #[inline]
+            fn read_from_buffer<B: instructor::Buffer>(buffer: &mut B) -> core::result::Result<Self, instructor::Error> {
+                let tag: #tag = instructor::Exstruct::<#endian>::read_from_buffer(buffer)?;
+                instructor::ExstructTagged::<#endian>::read_tagged_from_buffer(&tag, buffer)
+            }
+        }
     };
     Ok(output)
 }
@@ -198,4 +604,614 @@ mod tests {
         let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
         print!("{}", formatted);
     }
+
+    #[test]
+    fn print_data_enum() {
+        let input = syn::parse_quote! {
+            #[instructor(tag = u8)]
+            enum Data {
+                #[instructor(tag_value = 0x01)]
+                A {
+                    a: u8,
+                    b: u16
+                },
+                #[instructor(tag_value = 0x02)]
+                B(i32),
+                #[instructor(default, tag_value = 0xFF)]
+                C
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_select() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Signaling {
+                code: SignalingCodes,
+                id: u8,
+                #[instructor(select = "code")]
+                body: SignalingBody
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_generics() {
+        let input = syn::parse_quote! {
+            struct Frame<'a, T: Copy, U, const N: usize>
+            where
+                U: Default
+            {
+                header: u16,
+                body: T,
+                trailer: [u8; N],
+                marker: PhantomData<&'a U>
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_generics_bound() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big", bound = "T: Copy")]
+            struct Header<T> {
+                length: u16,
+                marker: PhantomData<T>
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_field_endian() {
+        let input = syn::parse_quote! {
+            struct Header {
+                magic: u32,
+                #[instructor(endian = "big")]
+                length: u16,
+                #[instructor(bitfield(u16), endian = "little")]
+                #[instructor(bits(0..4))]
+                a: u8,
+                #[instructor(bits(4..16))]
+                b: u16
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_counted() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Table {
+                num_entries: u8,
+                #[instructor(count = "num_entries")]
+                entries: Vec<u16>,
+                #[instructor(count_prefix = u16)]
+                names: Vec<[u8; 4]>
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_byte_len() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Message {
+                len: u8,
+                #[instructor(byte_len = "len")]
+                body: Body,
+                #[instructor(byte_len_prefix = u16)]
+                extension: Extension,
+                trailer: u8
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_constants() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big", magic = b"\x89PNG")]
+            struct Signature {
+                #[instructor(const = 0x0d0a_1a0a)]
+                line_endings: u32,
+                version: u8
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_padding() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little", align = 8, pad_byte = 0xff, strict_padding)]
+            struct Record {
+                kind: u8,
+                #[instructor(pad_before = 3)]
+                len: u32,
+                #[instructor(pad_after = 2)]
+                flags: u16
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_conditional() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Event {
+                version: u8,
+                flags: Flags,
+                #[instructor(if = "flags.contains(Flags::EXT)")]
+                extended: Option<u32>,
+                #[instructor(if = "version >= 2")]
+                timestamp: Option<u64>
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_calc() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Command {
+                code: u8,
+                #[instructor(calc = "data.len() as u16 + 1", verify_calc)]
+                length: u16,
+                id: u8,
+                data: Vec<u8>
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_checksum() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct Frame {
+                kind: u8,
+                len: u16,
+                #[instructor(checksum = instructor::checksum::Xor)]
+                header_checksum: u8,
+                payload: [u8; 4],
+                #[instructor(checksum = instructor::checksum::Crc32, covers = "kind..=payload")]
+                crc: u32
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_validate() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little", validate = "validate_range")]
+            struct Range {
+                #[instructor(validate = "validate_port")]
+                start: u16,
+                end: u16
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_with() {
+        let input = syn::parse_quote! {
+            struct Measurement {
+                #[instructor(with = "scaled")]
+                temperature: f32,
+                #[instructor(read_with = "timestamp::read", write_with = "timestamp::write")]
+                time: SystemTime,
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(0..4), with = "nibble")]
+                unit: Unit,
+                #[instructor(bits(4..8))]
+                channel: u8
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_skip() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Packet {
+                id: u16,
+                #[instructor(skip)]
+                cache: Vec<u8>,
+                #[instructor(skip, default = "Instant::now()")]
+                received: Instant,
+                len: u8
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_map() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct Reading {
+                #[instructor(map = "u16")]
+                voltage: Millivolts,
+                #[instructor(try_map = "u8")]
+                channel: Channel
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_msb0() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big", bit_order = "msb0")]
+            struct Ipv4Header {
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(0..4))]
+                version: u8,
+                #[instructor(bits(4..8))]
+                ihl: u8,
+                #[instructor(bitfield(u16, lsb0))]
+                #[instructor(bits(0..13))]
+                fragment_offset: u16,
+                #[instructor(bits(13..16))]
+                flags: u8
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_signed_bits() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "little")]
+            struct Motion {
+                #[instructor(bitfield(u16))]
+                #[instructor(bits(0..6))]
+                dx: i8,
+                #[instructor(bits(6..12))]
+                dy: i8,
+                #[instructor(bits(12..13))]
+                pressed: bool,
+                #[instructor(bits(13..16), signed)]
+                wheel: Wheel
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_exhaustive_bitfield() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct Control {
+                #[instructor(bitfield(u8, msb0, exhaustive))]
+                #[instructor(bits(0..1))]
+                poll: bool,
+                #[instructor(bits(1..8))]
+                sequence: u8,
+                #[instructor(bitfield(u24, exhaustive))]
+                #[instructor(bits(0..24))]
+                offset: u32
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_bit_stream() {
+        let input = syn::parse_quote! {
+            #[instructor(endian = "big")]
+            struct SliceHeader {
+                #[instructor(bits = 1)]
+                first_slice: bool,
+                #[instructor(bits = 5)]
+                slice_type: u8,
+                #[instructor(bits = 7)]
+                qp_delta: i8,
+                #[instructor(bits = 1, if = "slice_type == 2")]
+                long_term: Option<bool>,
+                #[instructor(bits = 8, byte_align)]
+                frame_num: u8,
+                length: u16
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_unknown_enum() {
+        let input = syn::parse_quote! {
+            #[repr(u8)]
+            enum Opcode {
+                Read = 0x01,
+                Write = 0x02,
+                #[instructor(unknown)]
+                Unknown(u8)
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_enum_values() {
+        let input = syn::parse_quote! {
+            #[repr(u8)]
+            enum Command {
+                Reset,
+                Status,
+                Read = 0x10,
+                Write,
+                #[instructor(values(0x20, 0x21))]
+                Erase,
+                #[instructor(value = ESCAPE)]
+                Escape
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_enum_ranges() {
+        let input = syn::parse_quote! {
+            #[repr(u8)]
+            enum Code {
+                Ping = 0x01,
+                Pong = 0x02,
+                #[instructor(range = 0x20..RESERVED_END)]
+                Reserved(u8),
+                #[instructor(range = 0xF0..=0xFF)]
+                Vendor(u8)
+            }
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn print_bitflags_modes() {
+        let input = syn::parse_quote! {
+            #[instructor(bitflags(truncate))]
+            struct Features(u8);
+        };
+
+        let output = derive_unpack(input).unwrap();
+        let formatted = prettyplease::unparse(&syn::parse2(output).unwrap());
+        print!("{}", formatted);
+    }
+
+    #[test]
+    fn reject_checksum_ranges_after_the_checksum() {
+        let input = syn::parse_quote! {
+            struct Frame {
+                #[instructor(checksum = Xor, covers = "b..=b")]
+                a: u8,
+                b: u8
+            }
+        };
+
+        let error = derive_unpack(input).unwrap_err();
+        assert_eq!(error.to_string(), "a checksum must cover a non-empty range of fields that does not start after it");
+    }
+
+    #[test]
+    fn reject_checksums_covering_themselves_within_nested_ranges() {
+        let input = syn::parse_quote! {
+            struct Frame {
+                a: u8,
+                #[instructor(checksum = Xor, covers = "a..=c")]
+                b: u8,
+                c: u8,
+                #[instructor(checksum = Xor, covers = "b..=c")]
+                d: u8
+            }
+        };
+
+        let error = derive_unpack(input).unwrap_err();
+        assert_eq!(error.to_string(), "a checksum covering its own field cannot be covered by a checksum nested in its range");
+    }
+
+    fn unpack_error(input: DeriveInput) -> String {
+        derive_unpack(input).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn reject_overlapping_bits() {
+        let input = syn::parse_quote! {
+            struct Header {
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(0..4))]
+                a: u8,
+                #[instructor(bits(3..8))]
+                b: u8
+            }
+        };
+
+        assert_eq!(unpack_error(input), "bits 3..8 overlap with bits 0..4");
+    }
+
+    #[test]
+    fn reject_bits_exceeding_the_storage() {
+        let input = syn::parse_quote! {
+            struct Header {
+                #[instructor(bitfield(u16))]
+                #[instructor(bits(0..12))]
+                a: u16,
+                #[instructor(bits(12..17))]
+                b: u8
+            }
+        };
+
+        assert_eq!(unpack_error(input), "bits 12..17 exceed the 16 bits of `u16`");
+    }
+
+    #[test]
+    fn reject_gaps_in_exhaustive_bitfields() {
+        let input = syn::parse_quote! {
+            struct Header {
+                #[instructor(bitfield(u16, exhaustive))]
+                #[instructor(bits(2..4))]
+                a: u8,
+                #[instructor(bits(8..12))]
+                b: u8
+            }
+        };
+
+        assert_eq!(unpack_error(input), "bits 0..2, 4..8, 12..16 are not assigned to any field");
+    }
+
+    #[test]
+    fn reject_malformed_bit_ranges() {
+        let empty = syn::parse_quote! {
+            struct Header {
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(4..4))]
+                a: u8
+            }
+        };
+        let open = syn::parse_quote! {
+            struct Header {
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(4..))]
+                a: u8
+            }
+        };
+        let single = syn::parse_quote! {
+            struct Header {
+                #[instructor(bitfield(u8))]
+                #[instructor(bits(4))]
+                a: u8
+            }
+        };
+
+        assert_eq!(unpack_error(empty), "bit ranges cannot be empty");
+        assert_eq!(unpack_error(open), "unexpected end of input, expected integer literal");
+        assert_eq!(unpack_error(single), "expected `..`");
+    }
+
+    #[test]
+    fn reject_bit_widths_exceeding_the_type() {
+        let zero = syn::parse_quote! {
+            struct Flags {
+                #[instructor(bits = 0)]
+                a: u8
+            }
+        };
+        let huge = syn::parse_quote! {
+            struct Flags {
+                #[instructor(bits = 70)]
+                a: u64
+            }
+        };
+        let wide = syn::parse_quote! {
+            struct Flags {
+                #[instructor(bits = 9)]
+                a: i8
+            }
+        };
+        let conditional = syn::parse_quote! {
+            struct Flags {
+                a: bool,
+                #[instructor(bits = 2, if = "a")]
+                b: Option<bool>
+            }
+        };
+        let mapped = syn::parse_quote! {
+            struct Flags {
+                #[instructor(bits = 17, map = "u16")]
+                a: Level
+            }
+        };
+        let custom = syn::parse_quote! {
+            struct Flags {
+                #[instructor(bits = 40)]
+                a: Level
+            }
+        };
+
+        assert_eq!(unpack_error(zero), "bit widths must be between 1 and 64");
+        assert_eq!(unpack_error(huge), "bit widths must be between 1 and 64");
+        assert_eq!(unpack_error(wide), "9 bits do not fit into `i8`");
+        assert_eq!(unpack_error(conditional), "2 bits do not fit into `bool`");
+        assert_eq!(unpack_error(mapped), "17 bits do not fit into `u16`");
+        assert!(derive_unpack(custom).is_ok());
+    }
 }
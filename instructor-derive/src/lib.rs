@@ -16,3 +16,11 @@ pub fn derive_pack(input: TokenStream) -> TokenStream {
         .unwrap_or_else(syn::Error::into_compile_error)
         .into()
 }
+
+#[proc_macro_derive(IntEnum, attributes(instructor))]
+pub fn derive_int_enum(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    instructor_derive_internals::derive_int_enum(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
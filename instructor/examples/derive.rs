@@ -1,7 +1,7 @@
 use bitflags::bitflags;
 use bytes::{Buf, BufMut, Bytes, BytesMut};
 use instructor::utils::Length;
-use instructor::{Buffer, DoubleEndedBufferMut, Exstruct, Instruct};
+use instructor::{Buffer, BufferMut, DoubleEndedBufferMut, Exstruct, Instruct};
 
 fn main() {
     let btpacket = &[0x00, 0x28, 0x0a, 0x00, 0x06, 0x00, 0x01, 0x00, 0x0a, 0x02, 0x02, 0x00, 0x02, 0x00];
@@ -35,6 +35,25 @@ fn main() {
     println!("{:02x?}", test.chunk());
     assert_eq!(test.chunk(), btpacket.as_slice());
 
+    let mut tagged = BytesMut::new();
+    tagged.write(Headers::L2cap(L2capHeader {
+        len: Length::new(0).unwrap(),
+        cid: 1
+    }));
+    let header: Headers = tagged.freeze().read().unwrap();
+    println!("{:?}", header);
+
+    let mut command = BytesMut::new();
+    command.write(SignalingCommand {
+        code: SignalingCodes::EchoRequest,
+        id: 3,
+        length: 0,
+        data: vec![0xde, 0xad]
+    });
+    let command: SignalingCommand = command.freeze().read().unwrap();
+    assert_eq!(command.length, 2);
+    println!("{:?}", command);
+
     //let mut test2 = BytesMut::new();
     //test2.put(data);
     //test2.write_front(&signaling);
@@ -70,6 +89,16 @@ struct SignalingHeader {
     length: Length<u16, 0>
 }
 
+#[derive(Debug, Exstruct, Instruct)]
+#[instructor(endian = "little")]
+struct SignalingCommand {
+    code: SignalingCodes,
+    id: u8,
+    #[instructor(calc = "data.len() as u16", verify_calc)]
+    length: u16,
+    data: Vec<u8>
+}
+
 #[derive(Debug, Exstruct, Instruct)]
 #[repr(u8)]
 enum SignalingCodes {
@@ -110,11 +139,14 @@ enum BroadcastFlag {
     BrEdrBroadcast = 0b01
 }
 
-#[derive(Debug, Instruct)]
-#[instructor(endian = "little")]
+#[derive(Debug, Exstruct, Instruct)]
+#[instructor(endian = "little", tag = u8)]
 enum Headers {
+    #[instructor(tag_value = 0x01)]
     Acl(AclHeader),
+    #[instructor(tag_value = 0x02)]
     L2cap(L2capHeader),
+    #[instructor(tag_value = 0x03)]
     Signaling(SignalingHeader)
 }
 
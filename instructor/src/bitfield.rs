@@ -1,9 +1,11 @@
 use std::mem::size_of;
 
-use crate::{Buffer, BufferMut, Endian, Error, Exstruct, Instruct};
+use crate::map::MapIndex;
+use crate::{BigEndian, Buffer, BufferMut, Endian, Error, ErrorKind, Exstruct, Instruct};
 
 pub trait BitStorage: Sized + Copy + Default {
     type Buffer: AsMut<[u8]> + Default;
+
     fn extract(&self, start: u32, end: u32) -> Self::Buffer;
 
     fn insert(&mut self, start: u32, end: u32, value: Self::Buffer);
@@ -19,7 +21,7 @@ macro_rules! impl_bitstorage_trait {
                 fn extract(&self, start: u32, end: u32) -> Self::Buffer {
                     debug_assert!(start < end);
                     debug_assert!(end <= Self::BITS);
-                    let mask = (1 << (end - start)) - 1;
+                    let mask = Self::MAX >> (Self::BITS - (end - start));
                     let masked = (self >> start) & mask;
                     masked.to_be_bytes()
                 }
@@ -27,7 +29,7 @@ macro_rules! impl_bitstorage_trait {
                 fn insert(&mut self, start: u32, end: u32, value: Self::Buffer) {
                     debug_assert!(start < end);
                     debug_assert!(end <= Self::BITS);
-                    let mask = (1 << (end - start)) - 1;
+                    let mask = Self::MAX >> (Self::BITS - (end - start));
                     let masked = (Self::from_be_bytes(value) & mask) << start;
                     *self |= masked;
                 }
@@ -39,16 +41,52 @@ macro_rules! impl_bitstorage_trait {
 
 impl_bitstorage_trait!(u8, u16, u32, u64, u128);
 
+/// Returns whether bit `index` of a big endian number is set.
+fn bit(bytes: &[u8], index: u32) -> bool {
+    match bytes.len().checked_sub(index as usize / 8 + 1) {
+        Some(byte) => bytes[byte] >> (index % 8) & 1 == 1,
+        None => false
+    }
+}
+
+/// Splits a big endian number at bit `from`, returning the index of the byte containing it
+/// together with the mask of the bits at and above `from` in that byte.
+fn split_at_bit(bytes: &[u8], from: u32) -> Option<(usize, u8)> {
+    let byte = bytes.len().checked_sub(from as usize / 8 + 1)?;
+    Some((byte, !((1 << (from % 8)) - 1)))
+}
+
+/// Sets all bits at and above `from`.
+fn fill_high_bits(bytes: &mut [u8], from: u32) {
+    if let Some((byte, mask)) = split_at_bit(bytes, from) {
+        bytes[..byte].fill(0xFF);
+        bytes[byte] |= mask;
+    }
+}
+
+/// Returns whether all bits at and above `from` match `fill`.
+fn high_bits_equal(bytes: &[u8], from: u32, fill: u8) -> bool {
+    match split_at_bit(bytes, from) {
+        Some((byte, mask)) => bytes[..byte].iter().all(|b| *b == fill) && bytes[byte] & mask == fill & mask,
+        None => true
+    }
+}
+
 pub struct BitBuffer<I: BitStorage> {
     storage: I,
     start: u32,
     end: u32,
-    remaining: usize
+    signed: bool,
+    // converts the big endian bytes of the selected bits into the byte order they are read and written in
+    reorder: fn(&mut [u8]),
+    remaining: usize,
+    // set for buffers that only check whether values fit, see `checked`
+    overflowed: Option<bool>
 }
 
 impl<I: BitStorage> BitBuffer<I> {
     #[inline]
-    pub fn new<E, B: Buffer + ?Sized>(source: &mut B) -> Result<Self, Error>
+    pub fn new<E, B: Buffer>(source: &mut B) -> Result<Self, Error>
     where
         I: Exstruct<E>,
         E: Endian
@@ -57,7 +95,10 @@ impl<I: BitStorage> BitBuffer<I> {
             storage: Exstruct::<E>::read_from_buffer(source)?,
             start: 0,
             end: 0,
-            remaining: source.remaining()
+            signed: false,
+            reorder: BigEndian::reorder_be,
+            remaining: source.remaining(),
+            overflowed: None
         })
     }
 }
@@ -69,14 +110,63 @@ impl<I: BitStorage> BitBuffer<I> {
             storage: I::default(),
             start: 0,
             end: 0,
-            remaining: 0
+            signed: false,
+            reorder: BigEndian::reorder_be,
+            remaining: 0,
+            overflowed: None
         }
     }
 
+    /// Creates an empty bitfield that records whether values fit into their bits instead of panicking,
+    /// so that they can be validated before anything is written.
+    #[inline]
+    pub fn checked() -> Self {
+        Self {
+            overflowed: Some(false),
+            ..Self::empty()
+        }
+    }
+
+    /// Whether a value written to a [`checked`](Self::checked) bitfield did not fit into its bits.
+    #[inline]
+    pub fn overflowed(&self) -> bool {
+        self.overflowed == Some(true)
+    }
+
+    /// Selects the bits `start..end` for the next read or write, treating them as an unsigned big endian number.
     #[inline]
     pub fn set_range(&mut self, start: u32, end: u32) {
         self.start = start;
         self.end = end;
+        self.signed = false;
+        self.reorder = BigEndian::reorder_be;
+    }
+
+    /// Like [`set_range`](Self::set_range), but bit 0 is the most significant bit of the storage.
+    ///
+    /// Panics if the range exceeds the storage.
+    #[inline]
+    pub fn set_range_msb0(&mut self, start: u32, end: u32) {
+        // the storage is as wide as its big endian representation
+        let bits = I::Buffer::default().as_mut().len() as u32 * 8;
+        match bits.checked_sub(end) {
+            Some(low) => self.set_range(low, bits - start),
+            None => panic!("Bits {}..{} exceed the {} bits of the storage", start, end, bits)
+        }
+    }
+
+    /// Treats the selected bits as a two's complement number until the next call to `set_range`.
+    /// Values read are sign-extended and values written may be negative.
+    #[inline]
+    pub fn set_signed(&mut self) {
+        self.signed = true;
+    }
+
+    /// Reads and writes the selected bits in the byte order of `E` until the next call to `set_range`,
+    /// for types that can only be read and written in that byte order.
+    #[inline]
+    pub fn set_endian<E: Endian>(&mut self) {
+        self.reorder = E::reorder_be;
     }
 }
 
@@ -85,11 +175,16 @@ impl<I: BitStorage> Buffer for BitBuffer<I> {
     fn try_copy_to_slice(&mut self, buf: &mut [u8]) -> Result<(), Error> {
         let mut shifted = self.storage.extract(self.start, self.end);
         let own = shifted.as_mut();
+        let width = self.end - self.start;
+        if self.signed && width > 0 && bit(own, width - 1) {
+            fill_high_bits(own, width);
+        }
         if own.len() < buf.len() {
-            return Err(Error::TooShort);
+            return Err(Error::new(ErrorKind::TooShort));
         }
         let start = own.len() - buf.len();
         buf.copy_from_slice(&own[start..]);
+        (self.reorder)(buf);
         Ok(())
     }
 
@@ -109,18 +204,111 @@ impl<I: BitStorage> BufferMut for BitBuffer<I> {
         let mut buffer = I::Buffer::default();
         {
             let buffer = buffer.as_mut();
-            let start = buffer
-                .len()
-                .checked_sub(buf.len())
-                .expect("Datatype exceeds size of bitfield");
+            let start = match (buffer.len().checked_sub(buf.len()), &mut self.overflowed) {
+                (Some(start), _) => start,
+                (None, Some(overflowed)) => {
+                    *overflowed = true;
+                    return;
+                }
+                (None, None) => panic!("Datatype exceeds size of bitfield")
+            };
             buffer[start..].copy_from_slice(buf);
+            (self.reorder)(&mut buffer[start..]);
+            let negative = self.signed && buffer.get(start).is_some_and(|byte| byte & 0x80 != 0);
+            let fill = if negative { 0xFF } else { 0 };
+            buffer[..start].fill(fill);
+            // the sign bit of a signed value has to be kept as well
+            let width = self.end - self.start;
+            let from = if self.signed { width.saturating_sub(1) } else { width };
+            let fits = high_bits_equal(buffer, from, fill);
+            match &mut self.overflowed {
+                Some(overflowed) => *overflowed |= !fits,
+                // validation is the place to reject values, this only guards against writing corrupted data
+                None => assert!(fits, "Value does not fit into {} bits", width)
+            }
         }
         self.storage.insert(self.start, self.end, buffer);
     }
 }
 
 impl<I: BitStorage + Instruct<E>, E: Endian> Instruct<E> for BitBuffer<I> {
-    fn write_to_buffer<B: BufferMut + ?Sized>(&self, buffer: &mut B) {
+    fn write_to_buffer<B: BufferMut>(&self, buffer: &mut B) {
         Instruct::<E>::write_to_buffer(&self.storage, buffer);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::u24;
+    use crate::{BigEndian, LittleEndian};
+
+    /// A storage type defined outside of this crate would look like this.
+    #[derive(Debug, Default, Copy, Clone, PartialEq)]
+    struct Nibbles(u16);
+
+    impl BitStorage for Nibbles {
+        type Buffer = [u8; 2];
+
+        fn extract(&self, start: u32, end: u32) -> Self::Buffer {
+            self.0.extract(start, end)
+        }
+
+        fn insert(&mut self, start: u32, end: u32, value: Self::Buffer) {
+            self.0.insert(start, end, value)
+        }
+    }
+
+    fn read_msb0<I: BitStorage>(storage: I, start: u32, end: u32) -> u8 {
+        let mut buffer = BitBuffer { storage, ..BitBuffer::empty() };
+        buffer.set_range_msb0(start, end);
+        buffer.read::<u8, BigEndian>().unwrap()
+    }
+
+    #[test]
+    fn msb0_counts_from_the_most_significant_bit() {
+        assert_eq!(read_msb0(0xA5u8, 0, 4), 0xA);
+        assert_eq!(read_msb0(0xA5u8, 4, 8), 0x5);
+        assert_eq!(read_msb0(u24::new(0xC0_0001), 0, 2), 0b11);
+        assert_eq!(read_msb0(u24::new(0xC0_0001), 23, 24), 1);
+        assert_eq!(read_msb0(Nibbles(0x1234), 4, 8), 0x2);
+    }
+
+    #[test]
+    fn checked_bitfields_record_values_that_dont_fit() {
+        let mut buffer = BitBuffer::<u8>::checked();
+        buffer.set_range(0, 3);
+        buffer.set_signed();
+        buffer.write::<i8, BigEndian>(-4);
+        assert!(!buffer.overflowed());
+        buffer.set_range(3, 7);
+        buffer.write::<u8, BigEndian>(16);
+        assert!(buffer.overflowed());
+        assert!(!BitBuffer::<u8>::empty().overflowed());
+    }
+
+    #[test]
+    fn endian_fixed_values_are_reordered() {
+        let mut buffer = BitBuffer::<u32>::empty();
+        buffer.set_range(4, 20);
+        buffer.set_endian::<LittleEndian>();
+        buffer.write::<u16, LittleEndian>(0xABCD);
+        assert_eq!(buffer.storage, 0xABCD0);
+        assert_eq!(buffer.read::<u16, LittleEndian>().unwrap(), 0xABCD);
+        buffer.set_range(4, 20);
+        assert_eq!(buffer.read::<u16, BigEndian>().unwrap(), 0xABCD);
+
+        buffer.set_range(20, 32);
+        buffer.set_signed();
+        buffer.set_endian::<LittleEndian>();
+        buffer.write::<i16, LittleEndian>(-2);
+        assert_eq!(buffer.storage >> 20, 0xFFE);
+        assert_eq!(buffer.read::<i16, LittleEndian>().unwrap(), -2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Bits 8..20 exceed the 16 bits of the storage")]
+    fn msb0_ranges_cannot_exceed_the_storage() {
+        read_msb0(Nibbles(0), 8, 20);
+    }
+}
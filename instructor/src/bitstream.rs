@@ -0,0 +1,366 @@
+use crate::{Buffer, BufferMut, Error};
+
+/// Types that can be read from a bit stream using a given number of bits.
+pub trait ExstructBits: Sized {
+    fn read_from_bits<B: Buffer>(reader: &mut BitReader<B>, bits: u32) -> Result<Self, Error>;
+}
+
+/// Types that can be written to a bit stream using a given number of bits.
+pub trait InstructBits {
+    fn write_to_bits<B: BufferMut>(&self, writer: &mut BitWriter<B>, bits: u32);
+}
+
+#[inline]
+fn low_bits(bits: u32) -> u8 {
+    ((1u16 << bits) - 1) as u8
+}
+
+/// Reads values of arbitrary bit widths from a buffer, most significant bit first.
+///
+/// Bits left over in a partially consumed byte are discarded once the reader is finished.
+pub struct BitReader<'a, B> {
+    buffer: &'a mut B,
+    current: u8,
+    available: u32
+}
+
+impl<'a, B: Buffer> BitReader<'a, B> {
+    pub fn new(buffer: &'a mut B) -> Self {
+        Self {
+            buffer,
+            current: 0,
+            available: 0
+        }
+    }
+
+    /// Reads the next `bits` bits as an unsigned number.
+    pub fn read_bits(&mut self, bits: u32) -> Result<u64, Error> {
+        assert!(bits <= u64::BITS, "Cannot read more than 64 bits at once");
+        let mut value = 0;
+        let mut needed = bits;
+        while needed > 0 {
+            if self.available == 0 {
+                let mut byte = [0];
+                self.buffer.try_copy_to_slice(&mut byte)?;
+                self.current = byte[0];
+                self.available = 8;
+            }
+            let take = needed.min(self.available);
+            let chunk = (self.current >> (self.available - take)) & low_bits(take);
+            value = (value << take) | u64::from(chunk);
+            self.available -= take;
+            needed -= take;
+        }
+        Ok(value)
+    }
+
+    /// Returns whether the next bit is the first bit of a byte.
+    #[inline]
+    pub fn is_aligned(&self) -> bool {
+        self.available == 0
+    }
+
+    /// Discards the remaining bits of the current byte.
+    #[inline]
+    pub fn align(&mut self) {
+        self.available = 0;
+    }
+
+    /// Discards the remaining bits of the current byte and returns the wrapped buffer.
+    #[inline]
+    pub fn finish(self) -> &'a mut B {
+        self.buffer
+    }
+}
+
+impl<'a, B: Buffer> Buffer for BitReader<'a, B> {
+    fn try_copy_to_slice(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        if self.is_aligned() {
+            return self.buffer.try_copy_to_slice(buf);
+        }
+        for byte in buf {
+            *byte = self.read_bits(8)? as u8;
+        }
+        Ok(())
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), Error> {
+        if self.is_aligned() {
+            return self.buffer.skip(n);
+        }
+        for _ in 0..n {
+            self.read_bits(8)?;
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn remaining(&self) -> usize {
+        // the bits left in the current byte never add up to a whole byte
+        self.buffer.remaining()
+    }
+}
+
+/// Writes values of arbitrary bit widths to a buffer, most significant bit first.
+///
+/// A partially filled byte is padded with zero bits once the writer is finished.
+pub struct BitWriter<'a, B> {
+    buffer: &'a mut B,
+    current: u8,
+    used: u32
+}
+
+impl<'a, B: BufferMut> BitWriter<'a, B> {
+    pub fn new(buffer: &'a mut B) -> Self {
+        Self {
+            buffer,
+            current: 0,
+            used: 0
+        }
+    }
+
+    /// Writes the lowest `bits` bits of `value`.
+    ///
+    /// Panics if `value` does not fit into `bits` bits.
+    pub fn write_bits(&mut self, value: u64, bits: u32) {
+        assert!(bits <= u64::BITS, "Cannot write more than 64 bits at once");
+        assert!(bits == u64::BITS || value >> bits == 0, "Value does not fit into {} bits", bits);
+        let mut remaining = bits;
+        while remaining > 0 {
+            let take = remaining.min(8 - self.used);
+            let chunk = (value >> (remaining - take)) as u8 & low_bits(take);
+            self.current |= chunk << (8 - self.used - take);
+            self.used += take;
+            remaining -= take;
+            if self.used == 8 {
+                self.buffer.extend_from_slice(&[self.current]);
+                self.current = 0;
+                self.used = 0;
+            }
+        }
+    }
+
+    /// Returns whether the next bit is the first bit of a byte.
+    #[inline]
+    pub fn is_aligned(&self) -> bool {
+        self.used == 0
+    }
+
+    /// Pads the current byte with zero bits.
+    #[inline]
+    pub fn align(&mut self) {
+        if !self.is_aligned() {
+            self.write_bits(0, 8 - self.used);
+        }
+    }
+
+    /// Pads the current byte with zero bits and returns the wrapped buffer.
+    #[inline]
+    pub fn finish(mut self) -> &'a mut B {
+        self.align();
+        self.buffer
+    }
+}
+
+impl<'a, B: BufferMut> BufferMut for BitWriter<'a, B> {
+    fn extend_from_slice(&mut self, buf: &[u8]) {
+        if self.is_aligned() {
+            return self.buffer.extend_from_slice(buf);
+        }
+        for byte in buf {
+            self.write_bits(u64::from(*byte), 8);
+        }
+    }
+}
+
+macro_rules! impl_unsigned_bits {
+    ($($ty:ident),+) => {
+        $(
+            impl ExstructBits for $ty {
+                #[inline]
+                fn read_from_bits<B: Buffer>(reader: &mut BitReader<B>, bits: u32) -> Result<Self, Error> {
+                    let value = reader.read_bits(bits)?;
+                    Self::try_from(value).map_err(|_| Error::invalid_value(value))
+                }
+            }
+
+            impl InstructBits for $ty {
+                #[inline]
+                fn write_to_bits<B: BufferMut>(&self, writer: &mut BitWriter<B>, bits: u32) {
+                    writer.write_bits(u64::from(*self), bits);
+                }
+            }
+        )*
+    }
+}
+
+impl_unsigned_bits!(u8, u16, u32, u64);
+
+macro_rules! impl_signed_bits {
+    ($($ty:ident),+) => {
+        $(
+            impl ExstructBits for $ty {
+                #[inline]
+                fn read_from_bits<B: Buffer>(reader: &mut BitReader<B>, bits: u32) -> Result<Self, Error> {
+                    let value = reader.read_bits(bits)?;
+                    // sign-extend from the most significant bit that was read
+                    let shift = u64::BITS - bits;
+                    let extended = match bits {
+                        0 => 0,
+                        _ => ((value << shift) as i64) >> shift
+                    };
+                    Self::try_from(extended).map_err(|_| Error::invalid_value(value))
+                }
+            }
+
+            impl InstructBits for $ty {
+                #[inline]
+                fn write_to_bits<B: BufferMut>(&self, writer: &mut BitWriter<B>, bits: u32) {
+                    let value = i64::from(*self);
+                    let fits = match bits {
+                        0 => value == 0,
+                        64.. => true,
+                        _ => (-1..=0).contains(&(value >> (bits - 1)))
+                    };
+                    assert!(fits, "Value does not fit into {} bits", bits);
+                    let mask = u64::MAX.checked_shr(u64::BITS - bits).unwrap_or(0);
+                    writer.write_bits(value as u64 & mask, bits);
+                }
+            }
+        )*
+    }
+}
+
+impl_signed_bits!(i8, i16, i32, i64);
+
+impl ExstructBits for bool {
+    #[inline]
+    fn read_from_bits<B: Buffer>(reader: &mut BitReader<B>, bits: u32) -> Result<Self, Error> {
+        match reader.read_bits(bits)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            value => Err(Error::invalid_value(value))
+        }
+    }
+}
+
+impl InstructBits for bool {
+    #[inline]
+    fn write_to_bits<B: BufferMut>(&self, writer: &mut BitWriter<B>, bits: u32) {
+        writer.write_bits(u64::from(*self), bits);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorKind;
+
+    #[test]
+    fn reads_across_byte_boundaries() {
+        let mut data: &[u8] = &[0b1011_0110, 0b0101_1100, 0xAB];
+        let mut reader = BitReader::new(&mut data);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b101);
+        assert_eq!(reader.read_bits(10).unwrap(), 0b10_1100_1011);
+        assert!(!reader.is_aligned());
+        assert_eq!(reader.read_bits(0).unwrap(), 0);
+        assert_eq!(reader.read_bits(3).unwrap(), 0b100);
+        assert!(reader.is_aligned());
+        assert_eq!(reader.read_bits(8).unwrap(), 0xAB);
+        assert_eq!(reader.read_bits(1).unwrap_err().kind(), ErrorKind::TooShort);
+    }
+
+    #[test]
+    fn reads_whole_bytes_while_unaligned() {
+        let mut data: &[u8] = &[0x12, 0x34, 0x56];
+        let mut reader = BitReader::new(&mut data);
+        assert_eq!(reader.read_bits(4).unwrap(), 0x1);
+        let mut bytes = [0; 2];
+        reader.try_copy_to_slice(&mut bytes).unwrap();
+        assert_eq!(bytes, [0x23, 0x45]);
+        assert_eq!(reader.read_bits(4).unwrap(), 0x6);
+        assert_eq!(reader.read_bits(64).unwrap_err().kind(), ErrorKind::TooShort);
+    }
+
+    #[test]
+    fn align_discards_the_rest_of_the_byte() {
+        let mut data: &[u8] = &[0xFF, 0x80, 0x01];
+        let mut reader = BitReader::new(&mut data);
+        reader.align();
+        assert_eq!(reader.read_bits(2).unwrap(), 0b11);
+        reader.align();
+        assert!(reader.is_aligned());
+        assert_eq!(reader.read_bits(1).unwrap(), 1);
+        assert_eq!(reader.finish().remaining(), 1);
+    }
+
+    #[test]
+    fn writes_across_byte_boundaries() {
+        let mut data = Vec::new();
+        let mut writer = BitWriter::new(&mut data);
+        writer.write_bits(0b101, 3);
+        writer.write_bits(0b10_1100_1011, 10);
+        writer.write_bits(0, 0);
+        writer.write_bits(0b100, 3);
+        assert!(writer.is_aligned());
+        writer.write_bits(u64::MAX, 64);
+        writer.finish();
+        assert_eq!(data, [0b1011_0110, 0b0101_1100, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn align_and_finish_pad_with_zeros() {
+        let mut data = Vec::new();
+        let mut writer = BitWriter::new(&mut data);
+        writer.align();
+        writer.write_bits(1, 1);
+        writer.align();
+        writer.align();
+        writer.write_bits(0b11, 2);
+        writer.extend_from_slice(&[0xAB]);
+        writer.finish();
+        assert_eq!(data, [0x80, 0b1110_1010, 0b1100_0000]);
+    }
+
+    #[test]
+    fn signed_values_are_sign_extended() {
+        let mut data = Vec::new();
+        let mut writer = BitWriter::new(&mut data);
+        (-3i8).write_to_bits(&mut writer, 3);
+        3i16.write_to_bits(&mut writer, 3);
+        i64::MIN.write_to_bits(&mut writer, 64);
+        (-1i32).write_to_bits(&mut writer, 2);
+        writer.finish();
+        assert_eq!(data[0], 0b1010_1110);
+
+        let mut data = data.as_slice();
+        let mut reader = BitReader::new(&mut data);
+        assert_eq!(i8::read_from_bits(&mut reader, 3).unwrap(), -3);
+        assert_eq!(i16::read_from_bits(&mut reader, 3).unwrap(), 3);
+        assert_eq!(i64::read_from_bits(&mut reader, 64).unwrap(), i64::MIN);
+        assert_eq!(i32::read_from_bits(&mut reader, 2).unwrap(), -1);
+    }
+
+    #[test]
+    fn values_that_dont_fit_the_type_are_rejected() {
+        let mut data: &[u8] = &[0xFF, 0xBF, 0xFF];
+        let mut reader = BitReader::new(&mut data);
+        assert_eq!(u8::read_from_bits(&mut reader, 9).unwrap_err().kind(), ErrorKind::InvalidValue);
+        assert_eq!(i8::read_from_bits(&mut reader, 9).unwrap_err().kind(), ErrorKind::InvalidValue);
+        assert_eq!(bool::read_from_bits(&mut reader, 2).unwrap_err().kind(), ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    #[should_panic(expected = "Value does not fit into 3 bits")]
+    fn signed_values_must_fit_their_bits() {
+        let mut data = Vec::new();
+        4i8.write_to_bits(&mut BitWriter::new(&mut data), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Value does not fit into 4 bits")]
+    fn unsigned_values_must_fit_their_bits() {
+        let mut data = Vec::new();
+        BitWriter::new(&mut data).write_bits(16, 4);
+    }
+}
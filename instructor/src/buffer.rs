@@ -2,7 +2,8 @@ use std::ops::DerefMut;
 
 use bytes::{Buf, BufMut};
 
-use crate::{BigEndian, Endian, Error, Exstruct, Instruct, LittleEndian, NativeEndian};
+use crate::utils::Limit;
+use crate::{BigEndian, Endian, Error, ErrorKind, Exstruct, Instruct, LittleEndian, NativeEndian};
 
 pub trait Buffer: Sized {
     fn try_copy_to_slice(&mut self, buf: &mut [u8]) -> Result<(), Error>;
@@ -44,8 +45,55 @@ pub trait Buffer: Sized {
         T::read_from_buffer(self)
     }
 
+    /// Reads exactly `count` consecutive elements.
+    #[inline]
+    fn read_vec<T, E>(&mut self, count: usize) -> Result<Vec<T>, Error>
+    where
+        T: Exstruct<E>,
+        E: Endian
+    {
+        let mut vec = Vec::with_capacity(count.min(self.remaining()));
+        for _ in 0..count {
+            vec.push(T::read_from_buffer(self)?);
+        }
+        Ok(vec)
+    }
+
+    /// Reads a value that is confined to the next `len` bytes.
+    ///
+    /// Bytes that are not consumed by the value are skipped.
+    #[inline]
+    fn read_limited<T, E>(&mut self, len: usize) -> Result<T, Error>
+    where
+        T: Exstruct<E>,
+        E: Endian
+    {
+        if self.remaining() < len {
+            return Err(Error::new(ErrorKind::TooShort));
+        }
+        let mut limit = Limit::new(self, len);
+        let value = T::read_from_buffer(&mut limit)?;
+        limit.skip(limit.remaining())?;
+        Ok(value)
+    }
+
+    /// Skips `n` reserved bytes, failing with [`ErrorKind::ConstantMismatch`] if any of them differs from `fill`.
+    fn skip_reserved(&mut self, n: usize, fill: u8) -> Result<(), Error> {
+        let mut chunk = [0u8; 16];
+        let mut n = n;
+        while n > 0 {
+            let len = n.min(chunk.len());
+            self.try_copy_to_slice(&mut chunk[..len])?;
+            if let Some(byte) = chunk[..len].iter().find(|b| **b != fill) {
+                return Err(Error::new(ErrorKind::ConstantMismatch).with_value(*byte));
+            }
+            n -= len;
+        }
+        Ok(())
+    }
+
     fn finish(&self) -> Result<(), Error> {
-        (self.remaining() == 0).then_some(()).ok_or(Error::TooLong)
+        (self.remaining() == 0).then_some(()).ok_or(Error::new(ErrorKind::TooLong))
     }
 }
 
@@ -68,7 +116,7 @@ pub trait Buffer: Sized {
 impl<T: Buf> Buffer for T {
     fn try_copy_to_slice(&mut self, buf: &mut [u8]) -> Result<(), Error> {
         if Buf::remaining(self) < buf.len() {
-            return Err(Error::TooShort);
+            return Err(Error::new(ErrorKind::TooShort));
         }
         self.copy_to_slice(buf);
         Ok(())
@@ -76,7 +124,7 @@ impl<T: Buf> Buffer for T {
 
     fn skip(&mut self, n: usize) -> Result<(), Error> {
         if Buf::remaining(self) < n {
-            return Err(Error::TooShort);
+            return Err(Error::new(ErrorKind::TooShort));
         }
         self.advance(n);
         Ok(())
@@ -108,6 +156,29 @@ pub trait BufferMut: Sized {
         value.write_to_buffer(self);
     }
 
+    /// Validates the value before writing it, leaving the buffer untouched if it is rejected.
+    #[inline]
+    fn try_write<T, E>(&mut self, value: T) -> Result<(), Error>
+    where
+        T: Instruct<E>,
+        E: Endian
+    {
+        value.validate()?;
+        value.write_to_buffer(self);
+        Ok(())
+    }
+
+    #[inline]
+    fn try_write_ref<T, E>(&mut self, value: &T) -> Result<(), Error>
+    where
+        T: Instruct<E>,
+        E: Endian
+    {
+        value.validate()?;
+        value.write_to_buffer(self);
+        Ok(())
+    }
+
     #[inline]
     fn write_le<T>(&mut self, value: T)
     where
@@ -155,6 +226,17 @@ pub trait BufferMut: Sized {
     {
         value.write_to_buffer(self);
     }
+
+    /// Writes `n` copies of `fill`.
+    fn pad(&mut self, n: usize, fill: u8) {
+        let chunk = [fill; 16];
+        let mut n = n;
+        while n > 0 {
+            let len = n.min(chunk.len());
+            self.extend_from_slice(&chunk[..len]);
+            n -= len;
+        }
+    }
 }
 
 impl<T: BufMut> BufferMut for T {
@@ -0,0 +1,302 @@
+use crate::{Buffer, BufferMut, Error};
+
+/// An integrity check that is computed incrementally over a sequence of bytes.
+pub trait Checksum: Default {
+    type Output;
+
+    fn update(&mut self, bytes: &[u8]);
+
+    fn finish(&self) -> Self::Output;
+
+    /// Computes the checksum of `bytes` in one go.
+    fn checksum(bytes: &[u8]) -> Self::Output {
+        let mut checksum = Self::default();
+        checksum.update(bytes);
+        checksum.finish()
+    }
+}
+
+/// The ones' complement checksum used by IPv4, TCP, UDP and ICMP (RFC 1071).
+///
+/// These protocols store the checksum inside the data it covers, which the derive supports
+/// by letting a checksum field cover itself.
+#[derive(Debug, Default, Clone)]
+pub struct Internet {
+    sum: u64,
+    odd: Option<u8>
+}
+
+impl Checksum for Internet {
+    type Output = u16;
+
+    fn update(&mut self, mut bytes: &[u8]) {
+        if let (Some(high), Some((low, rest))) = (self.odd, bytes.split_first()) {
+            self.sum += u64::from(u16::from_be_bytes([high, *low]));
+            self.odd = None;
+            bytes = rest;
+        }
+        let mut words = bytes.chunks_exact(2);
+        for word in &mut words {
+            self.sum += u64::from(u16::from_be_bytes([word[0], word[1]]));
+        }
+        if let [last] = words.remainder() {
+            self.odd = Some(*last);
+        }
+    }
+
+    fn finish(&self) -> u16 {
+        let mut sum = self.sum + self.odd.map_or(0, |high| u64::from(high) << 8);
+        while sum > 0xFFFF {
+            sum = (sum & 0xFFFF) + (sum >> 16);
+        }
+        !(sum as u16)
+    }
+}
+
+/// CRC-16/CCITT-FALSE: polynomial `0x1021`, initial value `0xFFFF`, no reflection.
+#[derive(Debug, Clone)]
+pub struct Crc16Ccitt(u16);
+
+impl Crc16Ccitt {
+    const TABLE: [u16; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = (i as u16) << 8;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = match crc & 0x8000 {
+                    0 => crc << 1,
+                    _ => (crc << 1) ^ 0x1021
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+}
+
+impl Default for Crc16Ccitt {
+    fn default() -> Self {
+        Self(0xFFFF)
+    }
+}
+
+impl Checksum for Crc16Ccitt {
+    type Output = u16;
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 << 8) ^ Self::TABLE[usize::from((self.0 >> 8) as u8 ^ byte)];
+        }
+    }
+
+    fn finish(&self) -> u16 {
+        self.0
+    }
+}
+
+/// CRC-32 as used by Ethernet, zlib and PNG: reflected polynomial `0xEDB88320`.
+#[derive(Debug, Clone)]
+pub struct Crc32(u32);
+
+impl Crc32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = match crc & 1 {
+                    0 => crc >> 1,
+                    _ => (crc >> 1) ^ 0xEDB8_8320
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+}
+
+impl Default for Crc32 {
+    fn default() -> Self {
+        Self(0xFFFF_FFFF)
+    }
+}
+
+impl Checksum for Crc32 {
+    type Output = u32;
+
+    fn update(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 >> 8) ^ Self::TABLE[usize::from(self.0 as u8 ^ byte)];
+        }
+    }
+
+    fn finish(&self) -> u32 {
+        !self.0
+    }
+}
+
+/// The XOR of all bytes.
+#[derive(Debug, Default, Clone)]
+pub struct Xor(u8);
+
+impl Checksum for Xor {
+    type Output = u8;
+
+    fn update(&mut self, bytes: &[u8]) {
+        self.0 = bytes.iter().fold(self.0, |acc, byte| acc ^ byte);
+    }
+
+    fn finish(&self) -> u8 {
+        self.0
+    }
+}
+
+/// Wraps a buffer and feeds every byte that is read from or written to it into a checksum.
+pub struct Digest<'a, B, C> {
+    buffer: &'a mut B,
+    checksum: C
+}
+
+impl<'a, B, C: Checksum> Digest<'a, B, C> {
+    pub fn new(buffer: &'a mut B) -> Self {
+        Self {
+            buffer,
+            checksum: C::default()
+        }
+    }
+
+    /// Returns the wrapped buffer together with the checksum of all bytes that passed through.
+    pub fn finish(self) -> (&'a mut B, C::Output) {
+        (self.buffer, self.checksum.finish())
+    }
+}
+
+impl<'a, B: Buffer, C: Checksum> Digest<'a, B, C> {
+    /// Reads a value directly from the wrapped buffer, feeding zeros into the checksum in place of its bytes.
+    ///
+    /// This is how a checksum field that is covered by its own checksum is read.
+    pub fn read_zeroed<T>(&mut self, read: impl FnOnce(&mut B) -> Result<T, Error>) -> Result<T, Error> {
+        let remaining = self.buffer.remaining();
+        let value = read(self.buffer)?;
+        let zeros = [0u8; 16];
+        let mut n = remaining - self.buffer.remaining();
+        while n > 0 {
+            let len = n.min(zeros.len());
+            self.checksum.update(&zeros[..len]);
+            n -= len;
+        }
+        Ok(value)
+    }
+}
+
+impl<'a, B: Buffer, C: Checksum> Buffer for Digest<'a, B, C> {
+    fn try_copy_to_slice(&mut self, buf: &mut [u8]) -> Result<(), Error> {
+        self.buffer.try_copy_to_slice(buf)?;
+        self.checksum.update(buf);
+        Ok(())
+    }
+
+    fn skip(&mut self, n: usize) -> Result<(), Error> {
+        // skipped bytes are still covered by the checksum
+        let mut chunk = [0u8; 16];
+        let mut n = n;
+        while n > 0 {
+            let len = n.min(chunk.len());
+            self.try_copy_to_slice(&mut chunk[..len])?;
+            n -= len;
+        }
+        Ok(())
+    }
+
+    fn remaining(&self) -> usize {
+        self.buffer.remaining()
+    }
+}
+
+impl<'a, B: BufferMut, C: Checksum> BufferMut for Digest<'a, B, C> {
+    fn extend_from_slice(&mut self, buf: &[u8]) {
+        self.buffer.extend_from_slice(buf);
+        self.checksum.update(buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CHECK: &[u8] = b"123456789";
+
+    #[test]
+    fn crc16_ccitt_false() {
+        assert_eq!(Crc16Ccitt::checksum(CHECK), 0x29B1);
+        assert_eq!(Crc16Ccitt::checksum(&[]), 0xFFFF);
+    }
+
+    #[test]
+    fn crc32() {
+        assert_eq!(Crc32::checksum(CHECK), 0xCBF4_3926);
+        assert_eq!(Crc32::checksum(&[]), 0);
+    }
+
+    #[test]
+    fn internet() {
+        // the example from RFC 1071, section 3
+        let data = [0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7];
+        assert_eq!(Internet::checksum(&data), !0xddf2);
+        // an odd number of bytes is padded with a zero byte
+        assert_eq!(Internet::checksum(&[0x12]), !0x1200);
+    }
+
+    #[test]
+    fn internet_of_an_ipv4_header() {
+        let mut header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7
+        ];
+        assert_eq!(Internet::checksum(&header), 0xb861);
+        header[10..12].copy_from_slice(&[0xb8, 0x61]);
+        assert_eq!(Internet::checksum(&header), 0);
+    }
+
+    #[test]
+    fn xor() {
+        assert_eq!(Xor::checksum(&[0x0f, 0xf0, 0x01]), 0xfe);
+    }
+
+    fn split<C: Checksum>(bytes: &[u8]) -> Vec<C::Output> {
+        (0..=bytes.len())
+            .map(|at| {
+                let mut checksum = C::default();
+                checksum.update(&bytes[..at]);
+                checksum.update(&bytes[at..]);
+                checksum.finish()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn split_updates() {
+        assert!(split::<Internet>(CHECK).iter().all(|c| *c == Internet::checksum(CHECK)));
+        assert!(split::<Crc16Ccitt>(CHECK).iter().all(|c| *c == 0x29B1));
+        assert!(split::<Crc32>(CHECK).iter().all(|c| *c == 0xCBF4_3926));
+        assert!(split::<Xor>(CHECK).iter().all(|c| *c == Xor::checksum(CHECK)));
+    }
+
+    #[test]
+    fn digest_reads_zeroed_values() {
+        let mut bytes: &[u8] = &[0x01, 0xff, 0x02];
+        let mut digest = Digest::<_, Xor>::new(&mut bytes);
+        digest.read::<u8, crate::BigEndian>().unwrap();
+        let zeroed = digest.read_zeroed(|buffer| buffer.read::<u8, crate::BigEndian>()).unwrap();
+        digest.read::<u8, crate::BigEndian>().unwrap();
+        assert_eq!(zeroed, 0xff);
+        assert_eq!(digest.finish().1, 0x03);
+    }
+}
@@ -1,22 +1,228 @@
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
-pub enum Error {
+pub enum ErrorKind {
     TooShort,
     TooLong,
     InvalidValue,
-    UnexpectedLength
+    UnexpectedLength,
+    ConstantMismatch,
+    ChecksumMismatch
 }
 
-impl Display for Error {
+impl Display for ErrorKind {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
-            Error::TooShort => write!(f, "Packet too short"),
-            Error::TooLong => write!(f, "Packet too long"),
-            Error::InvalidValue => write!(f, "Invalid value"),
-            Error::UnexpectedLength => write!(f, "Unexpected length")
+            ErrorKind::TooShort => write!(f, "Packet too short"),
+            ErrorKind::TooLong => write!(f, "Packet too long"),
+            ErrorKind::InvalidValue => write!(f, "Invalid value"),
+            ErrorKind::UnexpectedLength => write!(f, "Unexpected length"),
+            ErrorKind::ConstantMismatch => write!(f, "Constant mismatch"),
+            ErrorKind::ChecksumMismatch => write!(f, "Checksum mismatch")
+        }
+    }
+}
+
+/// An error that occurred while extracting a value.
+///
+/// Besides the [`ErrorKind`] an error can carry additional context:
+/// the byte offset at which the failing field begins, the path of that field
+/// and the offending raw value. The context is only allocated when it is attached,
+/// so creating a plain error stays cheap.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Error {
+    kind: ErrorKind,
+    context: Option<Box<Context>>
+}
+
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+struct Context {
+    offset: Option<usize>,
+    // how far parsing got past `offset`, to carry the offset over to enclosing types
+    consumed: Option<usize>,
+    type_name: Option<&'static str>,
+    fields: Vec<&'static str>,
+    value: Option<u128>
+}
+
+impl Error {
+    #[inline]
+    pub const fn new(kind: ErrorKind) -> Self {
+        Self { kind, context: None }
+    }
+
+    #[cold]
+    pub fn invalid_value<V: RawValue>(value: V) -> Self {
+        Self::new(ErrorKind::InvalidValue).with_value(value)
+    }
+
+    #[inline]
+    pub fn kind(&self) -> ErrorKind {
+        self.kind
+    }
+
+    /// The byte offset at which the innermost failing field begins, relative to the start of the outermost derived type.
+    pub fn offset(&self) -> Option<usize> {
+        self.context.as_ref().and_then(|c| c.offset)
+    }
+
+    /// The name of the outermost derived type that was being extracted.
+    pub fn type_name(&self) -> Option<&'static str> {
+        self.context.as_ref().and_then(|c| c.type_name)
+    }
+
+    /// The path of fields leading to the error, starting with the outermost field.
+    pub fn fields(&self) -> &[&'static str] {
+        self.context.as_ref().map_or(&[], |c| c.fields.as_slice())
+    }
+
+    /// The raw value that could not be converted.
+    pub fn value(&self) -> Option<u128> {
+        self.context.as_ref().and_then(|c| c.value)
+    }
+
+    #[cold]
+    pub fn with_value<V: RawValue>(mut self, value: V) -> Self {
+        self.context_mut().value = Some(value.into_raw());
+        self
+    }
+
+    /// Records that the error occurred in `field` of `type_name`, which begins `offset` bytes
+    /// into the type, after `consumed` bytes of the type had been read.
+    ///
+    /// This is called by the derived implementations while the error bubbles up,
+    /// so the outermost call determines the type name. The offset keeps pointing at the
+    /// start of the innermost field, translated to the outermost type.
+    #[cold]
+    pub fn with_field(self, type_name: &'static str, field: &'static str, offset: usize, consumed: usize) -> Self {
+        let mut error = self.in_field(type_name, field);
+        let context = error.context_mut();
+        context.offset = Some(match (context.offset, context.consumed) {
+            (Some(inner_offset), Some(inner_consumed)) => consumed.saturating_sub(inner_consumed.saturating_sub(inner_offset)),
+            _ => offset
+        });
+        context.consumed = Some(consumed);
+        error
+    }
+
+    /// Records that the error occurred in `field` of `type_name` without an offset,
+    /// for example when a value is rejected before it is written.
+    #[cold]
+    pub fn in_field(mut self, type_name: &'static str, field: &'static str) -> Self {
+        let context = self.context_mut();
+        context.type_name = Some(type_name);
+        context.fields.insert(0, field);
+        self
+    }
+
+    fn context_mut(&mut self) -> &mut Context {
+        self.context.get_or_insert_with(Default::default)
+    }
+}
+
+impl From<ErrorKind> for Error {
+    #[inline]
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+impl PartialEq<ErrorKind> for Error {
+    fn eq(&self, other: &ErrorKind) -> bool {
+        self.kind == *other
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.kind, f)?;
+        if let Some(value) = self.value() {
+            write!(f, " {:#x}", value)?;
+        }
+        if let Some(type_name) = self.type_name() {
+            write!(f, " in {}", type_name)?;
+            for field in self.fields() {
+                write!(f, ".{}", field)?;
+            }
+        }
+        if let Some(offset) = self.offset() {
+            write!(f, " at offset {}", offset)?;
         }
+        Ok(())
     }
 }
 
 impl std::error::Error for Error {}
+
+/// Primitive values that can be attached to an [`Error`].
+///
+/// Signed values are sign extended.
+pub trait RawValue: Copy {
+    fn into_raw(self) -> u128;
+}
+
+macro_rules! impl_raw_value {
+    ($($t:ident),+) => {
+        $(
+            impl RawValue for $t {
+                #[inline]
+                fn into_raw(self) -> u128 {
+                    self as u128
+                }
+            }
+        )*
+    }
+}
+
+impl_raw_value!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, bool);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_errors_have_no_context() {
+        let error = Error::new(ErrorKind::TooShort);
+        assert_eq!(error, ErrorKind::TooShort);
+        assert_ne!(error, ErrorKind::TooLong);
+        assert_eq!(error.offset(), None);
+        assert_eq!(error.type_name(), None);
+        assert!(error.fields().is_empty());
+        assert_eq!(error.to_string(), "Packet too short");
+    }
+
+    #[test]
+    fn invalid_values_are_recorded() {
+        let error = Error::invalid_value(-1i8);
+        assert_eq!(error.kind(), ErrorKind::InvalidValue);
+        assert_eq!(error.value(), Some(u128::MAX));
+        assert_eq!(Error::invalid_value(7u8).to_string(), "Invalid value 0x7");
+    }
+
+    #[test]
+    fn fields_are_prepended() {
+        let error = Error::invalid_value(7u8)
+            .with_field("SignalingHeader", "code", 1, 2)
+            .in_field("Packet", "header");
+        assert_eq!(error.type_name(), Some("Packet"));
+        assert_eq!(error.fields(), ["header", "code"]);
+        assert_eq!(error, ErrorKind::InvalidValue);
+    }
+
+    #[test]
+    fn offset_names_the_start_of_the_field() {
+        let error = Error::invalid_value(7u8).with_field("SignalingHeader", "code", 1, 2);
+        assert_eq!(error.offset(), Some(1));
+        assert_eq!(error.to_string(), "Invalid value 0x7 in SignalingHeader.code at offset 1");
+    }
+
+    #[test]
+    fn offset_is_carried_over_to_enclosing_types() {
+        // `code` begins one byte into the header, which itself begins four bytes into the packet
+        let error = Error::new(ErrorKind::TooShort)
+            .with_field("SignalingHeader", "code", 1, 3)
+            .with_field("Packet", "header", 4, 7);
+        assert_eq!(error.offset(), Some(5));
+        assert_eq!(error.to_string(), "Packet too short in Packet.header.code at offset 5");
+    }
+}
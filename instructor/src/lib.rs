@@ -1,5 +1,7 @@
 mod bitfield;
+mod bitstream;
 mod buffer;
+pub mod checksum;
 mod error;
 mod pack;
 mod unpack;
@@ -7,12 +9,13 @@ pub mod utils;
 mod size;
 
 pub use bitfield::{BitBuffer, BitStorage};
+pub use bitstream::{BitReader, BitWriter, ExstructBits, InstructBits};
 pub use buffer::{Buffer, BufferMut, DoubleEndedBufferMut};
-pub use error::Error;
+pub use error::{Error, ErrorKind, RawValue};
 #[cfg(feature = "derive")]
-pub use instructor_derive::{Exstruct, Instruct};
-pub use pack::Instruct;
-pub use unpack::Exstruct;
+pub use instructor_derive::{Exstruct, Instruct, IntEnum};
+pub use pack::{Instruct, InstructTagged};
+pub use unpack::{Exstruct, ExstructTagged};
 pub use size::ByteSize;
 
 pub struct LittleEndian;
@@ -37,17 +40,26 @@ mod map {
 
     pub trait MapIndex {
         fn map_index(n: usize, m: usize) -> Range<usize>;
+
+        /// Converts big endian bytes into this byte order and back.
+        fn reorder_be(bytes: &mut [u8]);
     }
 
     impl MapIndex for LittleEndian {
         fn map_index(n: usize, _: usize) -> Range<usize> {
             0..n
         }
+
+        fn reorder_be(bytes: &mut [u8]) {
+            bytes.reverse();
+        }
     }
 
     impl MapIndex for BigEndian {
         fn map_index(n: usize, m: usize) -> Range<usize> {
             (m - n)..m
         }
+
+        fn reorder_be(_: &mut [u8]) {}
     }
 }
@@ -1,11 +1,38 @@
+use std::marker::PhantomData;
+
 use bytes::Bytes;
-use crate::{BufferMut, Endian};
+
+use crate::{BufferMut, Endian, Error};
 
 pub trait Instruct<E: Endian>
 where
     Self: Sized
 {
     fn write_to_buffer<B: BufferMut>(&self, buffer: &mut B);
+
+    /// Checks whether the value can be written.
+    ///
+    /// Writing itself never fails, so this hook allows rejecting values before they are written.
+    /// It is not called by `write_to_buffer`, see [`BufferMut::try_write`].
+    #[inline]
+    fn validate(&self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Writes a type whose tag is stored separately, e.g. in a preceding header field.
+pub trait InstructTagged<E: Endian> {
+    type Tag;
+
+    fn matches_tag(&self, tag: &Self::Tag) -> bool;
+
+    fn write_untagged_to_buffer<B: BufferMut>(&self, buffer: &mut B);
+
+    /// Checks whether the value can be written, see [`Instruct::validate`].
+    #[inline]
+    fn validate(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 impl<E: Endian, const N: usize> Instruct<E> for [u8; N] {
@@ -17,7 +44,12 @@ impl<E: Endian, const N: usize> Instruct<E> for [u8; N] {
 
 impl<E: Endian> Instruct<E> for () {
     #[inline]
-    fn write_to_buffer<B: BufferMut + ?Sized>(&self, _: &mut B) {}
+    fn write_to_buffer<B: BufferMut>(&self, _: &mut B) {}
+}
+
+impl<E: Endian, T: ?Sized> Instruct<E> for PhantomData<T> {
+    #[inline]
+    fn write_to_buffer<B: BufferMut>(&self, _: &mut B) {}
 }
 
 impl<E: Endian> Instruct<E> for bool {
@@ -41,6 +73,26 @@ impl<E: Endian, T: Instruct<E>> Instruct<E> for Vec<T> {
             buffer.write_ref::<T, E>(item);
         }
     }
+
+    #[inline]
+    fn validate(&self) -> Result<(), Error> {
+        self.iter().try_for_each(T::validate)
+    }
+}
+
+/// Writes the value if it is present and nothing otherwise.
+impl<E: Endian, T: Instruct<E>> Instruct<E> for Option<T> {
+    #[inline]
+    fn write_to_buffer<B: BufferMut>(&self, buffer: &mut B) {
+        if let Some(value) = self {
+            buffer.write_ref::<T, E>(value);
+        }
+    }
+
+    #[inline]
+    fn validate(&self) -> Result<(), Error> {
+        self.as_ref().map_or(Ok(()), T::validate)
+    }
 }
 
 impl<E, T1, T2> Instruct<E> for (T1, T2)
@@ -93,7 +145,7 @@ macro_rules! impl_prim_pack {
         $(
             impl<E: Endian> Instruct<E> for $t {
                 #[inline]
-                fn write_to_buffer<B: BufferMut + ?Sized>(&self, buffer: &mut B) {
+                fn write_to_buffer<B: BufferMut>(&self, buffer: &mut B) {
                     buffer.extend_from_slice(&<E as WritePrimitive>::$t(*self));
                 }
             }
@@ -1,4 +1,6 @@
+use std::marker::PhantomData;
 use std::mem::size_of;
+
 use crate::{Buffer, Endian, Error, NativeEndian};
 
 pub trait Exstruct<E: Endian>
@@ -8,6 +10,16 @@ where
     fn read_from_buffer<B: Buffer>(buffer: &mut B) -> Result<Self, Error>;
 }
 
+/// Extracts a type whose layout is selected by a tag that was read beforehand.
+pub trait ExstructTagged<E: Endian>
+where
+    Self: Sized
+{
+    type Tag;
+
+    fn read_tagged_from_buffer<B: Buffer>(tag: &Self::Tag, buffer: &mut B) -> Result<Self, Error>;
+}
+
 impl<E: Endian, const N: usize> Exstruct<E> for [u8; N] {
     #[inline]
     fn read_from_buffer<B: Buffer>(buffer: &mut B) -> Result<Self, Error> {
@@ -24,6 +36,13 @@ impl<E: Endian> Exstruct<E> for () {
     }
 }
 
+impl<E: Endian, T: ?Sized> Exstruct<E> for PhantomData<T> {
+    #[inline]
+    fn read_from_buffer<B: Buffer>(_: &mut B) -> Result<Self, Error> {
+        Ok(PhantomData)
+    }
+}
+
 impl<E: Endian> Exstruct<E> for bool {
     #[inline]
     fn read_from_buffer<B: Buffer>(buffer: &mut B) -> Result<Self, Error> {
@@ -43,6 +62,17 @@ impl<E: Endian, T: Exstruct<E>> Exstruct<E> for Vec<T> {
 
 }
 
+/// Reads the value if there are bytes left in the buffer.
+impl<E: Endian, T: Exstruct<E>> Exstruct<E> for Option<T> {
+    #[inline]
+    fn read_from_buffer<B: Buffer>(buffer: &mut B) -> Result<Self, Error> {
+        match buffer.remaining() > 0 {
+            true => Ok(Some(buffer.read::<T, E>()?)),
+            false => Ok(None)
+        }
+    }
+}
+
 impl<E, T1, T2> Exstruct<E> for (T1, T2)
 where
     E: Endian,
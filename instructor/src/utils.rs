@@ -3,7 +3,7 @@ use std::ops::Deref;
 
 use crate::pack::WritePrimitive;
 use crate::unpack::ReadPrimitive;
-use crate::{BitStorage, Buffer, BufferMut, Endian, Error, Exstruct, Instruct};
+use crate::{BitStorage, Buffer, BufferMut, Endian, Error, ErrorKind, Exstruct, Instruct};
 
 #[derive(Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct Length<T, const OFFSET: isize>(T);
@@ -13,7 +13,7 @@ where
     T: TryFrom<usize>
 {
     pub fn new(len: usize) -> Result<Self, Error> {
-        let len = T::try_from(len).map_err(|_| Error::InvalidValue)?;
+        let len = T::try_from(len).map_err(|_| Error::new(ErrorKind::InvalidValue))?;
         Ok(Self(len))
     }
 
@@ -27,16 +27,16 @@ where
     T: TryInto<usize> + Exstruct<E> + Copy
 {
     #[inline]
-    fn read_from_buffer<B: Buffer + ?Sized>(buffer: &mut B) -> Result<Self, Error> {
+    fn read_from_buffer<B: Buffer>(buffer: &mut B) -> Result<Self, Error> {
         let len = buffer.read::<T, E>()?;
         buffer
             .remaining()
             .eq(&len
                 .try_into()
-                .map_err(|_| Error::InvalidValue)?
+                .map_err(|_| Error::new(ErrorKind::InvalidValue))?
                 .saturating_add_signed(OFFSET))
             .then_some(Self(len))
-            .ok_or(Error::UnexpectedLength)
+            .ok_or(Error::new(ErrorKind::UnexpectedLength))
     }
 }
 
@@ -45,7 +45,7 @@ where
     T: Instruct<E>
 {
     #[inline]
-    fn write_to_buffer<B: BufferMut + ?Sized>(&self, buffer: &mut B) {
+    fn write_to_buffer<B: BufferMut>(&self, buffer: &mut B) {
         buffer.write_ref(&self.0);
     }
 }
@@ -73,6 +73,8 @@ impl<T, const OFFSET: isize> Deref for Length<T, OFFSET> {
 pub trait DynBuffer {
     fn try_copy_to_slice(&mut self, buf: &mut [u8]) -> Result<(), Error>;
 
+    fn skip(&mut self, n: usize) -> Result<(), Error>;
+
     fn remaining(&self) -> usize;
 }
 
@@ -81,6 +83,10 @@ impl<T: Buffer> DynBuffer for T {
         T::try_copy_to_slice(self, buf)
     }
 
+    fn skip(&mut self, n: usize) -> Result<(), Error> {
+        T::skip(self, n)
+    }
+
     fn remaining(&self) -> usize {
         T::remaining(self)
     }
@@ -100,7 +106,7 @@ impl<'a> Limit<'a> {
 impl<'a> Buffer for Limit<'a> {
     fn try_copy_to_slice(&mut self, buf: &mut [u8]) -> Result<(), Error> {
         if self.remaining < buf.len() {
-            return Err(Error::TooShort);
+            return Err(Error::new(ErrorKind::TooShort));
         }
         self.buffer.try_copy_to_slice(buf)?;
         self.remaining -= buf.len();
@@ -109,8 +115,9 @@ impl<'a> Buffer for Limit<'a> {
 
     fn skip(&mut self, n: usize) -> Result<(), Error> {
         if self.remaining < n {
-            return Err(Error::TooShort);
+            return Err(Error::new(ErrorKind::TooShort));
         }
+        self.buffer.skip(n)?;
         self.remaining -= n;
         Ok(())
     }
@@ -120,6 +127,43 @@ impl<'a> Buffer for Limit<'a> {
     }
 }
 
+/// A [`BufferMut`] that keeps track of the number of bytes written through it.
+pub struct Counter<'a, B> {
+    buffer: &'a mut B,
+    written: usize
+}
+
+impl<'a, B: BufferMut> Counter<'a, B> {
+    pub fn new(buffer: &'a mut B) -> Self {
+        Self { buffer, written: 0 }
+    }
+
+    pub fn written(&self) -> usize {
+        self.written
+    }
+}
+
+impl<'a, B: BufferMut> BufferMut for Counter<'a, B> {
+    fn extend_from_slice(&mut self, buf: &[u8]) {
+        self.buffer.extend_from_slice(buf);
+        self.written += buf.len();
+    }
+}
+
+struct Discard;
+
+impl BufferMut for Discard {
+    fn extend_from_slice(&mut self, _: &[u8]) {}
+}
+
+/// Returns the number of bytes that writing `value` produces.
+pub fn encoded_len<E: Endian, T: Instruct<E>>(value: &T) -> usize {
+    let mut discard = Discard;
+    let mut counter = Counter::new(&mut discard);
+    value.write_to_buffer(&mut counter);
+    counter.written()
+}
+
 #[allow(non_camel_case_types)]
 #[derive(Default, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
 pub struct u24(u32);
@@ -137,7 +181,7 @@ impl u24 {
 
 impl<E: Endian> Exstruct<E> for u24 {
     #[inline]
-    fn read_from_buffer<B: Buffer + ?Sized>(buffer: &mut B) -> Result<Self, Error> {
+    fn read_from_buffer<B: Buffer>(buffer: &mut B) -> Result<Self, Error> {
         let mut data = [0; 4];
         buffer.try_copy_to_slice(&mut data[E::map_index(3, 4)])?;
         Ok(Self(<E as ReadPrimitive>::u32(data)))
@@ -146,7 +190,7 @@ impl<E: Endian> Exstruct<E> for u24 {
 
 impl<E: Endian> Instruct<E> for u24 {
     #[inline]
-    fn write_to_buffer<B: BufferMut + ?Sized>(&self, buffer: &mut B) {
+    fn write_to_buffer<B: BufferMut>(&self, buffer: &mut B) {
         let data = <E as WritePrimitive>::u32(self.0);
         buffer.extend_from_slice(&data[E::map_index(3, 4)]);
     }
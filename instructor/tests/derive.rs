@@ -0,0 +1,858 @@
+use bitflags::bitflags;
+use instructor::checksum::{Internet, Xor};
+use instructor::{Buffer, BufferMut, ErrorKind, LittleEndian};
+use instructor_derive::{Exstruct, Instruct, IntEnum};
+
+fn write<T: instructor::Instruct<LittleEndian>>(value: &T) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    buffer.write_ref::<T, LittleEndian>(value);
+    buffer
+}
+
+fn read<T: instructor::Exstruct<LittleEndian>>(mut bytes: &[u8]) -> Result<T, instructor::Error> {
+    let value = bytes.read::<T, LittleEndian>()?;
+    bytes.finish()?;
+    Ok(value)
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Exstruct, Instruct)]
+#[repr(u8)]
+enum Code {
+    Echo = 0x01,
+    Info = 0x02,
+    Other = 0x03
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(tag = Code)]
+enum Body {
+    #[instructor(tag_value = Code::Echo)]
+    Echo(u8),
+    #[instructor(tag_value = Code::Info)]
+    Info { kind: u16 }
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Selected {
+    code: Code,
+    #[instructor(select = "code")]
+    body: Body
+}
+
+#[test]
+fn select_reads_the_variant_of_an_earlier_field() {
+    let value = Selected { code: Code::Info, body: Body::Info { kind: 0x0102 } };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x02, 0x02, 0x01]);
+    assert_eq!(read::<Selected>(&bytes).unwrap(), value);
+    assert_eq!(read::<Selected>(&[0x01, 0x07]).unwrap().body, Body::Echo(7));
+}
+
+#[test]
+fn select_rejects_unknown_tags() {
+    let error = read::<Selected>(&[0x03, 0x00]).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["body"]);
+}
+
+#[test]
+fn select_rejects_mismatched_variants_before_writing() {
+    let mut bytes = Vec::new();
+    let error = bytes.try_write::<_, LittleEndian>(Selected { code: Code::Echo, body: Body::Info { kind: 0 } }).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["body"]);
+    assert!(bytes.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "`body` does not match the tag stored in `code`")]
+fn select_panics_on_mismatched_variant() {
+    write(&Selected { code: Code::Echo, body: Body::Info { kind: 0 } });
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Counted {
+    count: u8,
+    #[instructor(count = "count")]
+    items: Vec<u16>,
+    #[instructor(count_prefix = u8)]
+    tail: Vec<u8>
+}
+
+#[test]
+fn count_is_written_back() {
+    let value = Counted { count: 0, items: vec![1, 2], tail: vec![9] };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x02, 0x01, 0x00, 0x02, 0x00, 0x01, 0x09]);
+    assert_eq!(read::<Counted>(&bytes).unwrap(), Counted { count: 2, ..value });
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Paired {
+    count: u8,
+    #[instructor(count = "count")]
+    keys: Vec<u8>,
+    #[instructor(count = "count")]
+    values: Vec<u8>
+}
+
+#[test]
+fn counts_that_dont_fit_are_rejected_before_writing() {
+    let mut bytes = Vec::new();
+    let error = bytes.try_write::<_, LittleEndian>(Counted { count: 0, items: vec![0; 256], tail: vec![] }).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["items"]);
+    let error = bytes.try_write::<_, LittleEndian>(Counted { count: 0, items: vec![], tail: vec![0; 300] }).unwrap_err();
+    assert_eq!(error.fields(), ["tail"]);
+    let error = bytes.try_write::<_, LittleEndian>(Paired { count: 0, keys: vec![1, 2], values: vec![3] }).unwrap_err();
+    assert_eq!(error.fields(), ["values"]);
+    assert!(bytes.is_empty());
+
+    bytes.try_write::<_, LittleEndian>(Paired { count: 0, keys: vec![1, 2], values: vec![3, 4] }).unwrap();
+    assert_eq!(bytes, [0x02, 0x01, 0x02, 0x03, 0x04]);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Inner {
+    a: u8,
+    b: u16
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Framed {
+    len: u8,
+    #[instructor(byte_len = "len")]
+    inner: Inner,
+    #[instructor(byte_len_prefix = u16)]
+    prefixed: Inner,
+    end: u8
+}
+
+#[test]
+fn byte_len_is_written_back() {
+    let value = Framed { len: 0, inner: Inner { a: 1, b: 2 }, prefixed: Inner { a: 3, b: 4 }, end: 5 };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x03, 0x01, 0x02, 0x00, 0x03, 0x00, 0x03, 0x04, 0x00, 0x05]);
+    assert_eq!(read::<Framed>(&bytes).unwrap(), Framed { len: 3, ..value });
+}
+
+#[test]
+fn byte_len_skips_unused_bytes() {
+    let value = read::<Framed>(&[0x04, 0x01, 0x02, 0x00, 0xff, 0x03, 0x00, 0x03, 0x04, 0x00, 0x05]).unwrap();
+    assert_eq!(value.inner, Inner { a: 1, b: 2 });
+    assert_eq!(value.end, 5);
+    assert_eq!(read::<Framed>(&[0x02, 0x01, 0x02, 0x00]).unwrap_err(), ErrorKind::TooShort);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Blobs {
+    len: u8,
+    #[instructor(byte_len = "len")]
+    first: Vec<u8>,
+    #[instructor(byte_len_prefix = u8)]
+    second: Vec<u8>
+}
+
+#[test]
+fn lengths_that_dont_fit_are_rejected_before_writing() {
+    let mut bytes = Vec::new();
+    let error = bytes.try_write::<_, LittleEndian>(Blobs { len: 0, first: vec![0; 256], second: vec![] }).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["first"]);
+    let error = bytes.try_write::<_, LittleEndian>(Blobs { len: 0, first: vec![], second: vec![0; 256] }).unwrap_err();
+    assert_eq!(error.fields(), ["second"]);
+    assert!(bytes.is_empty());
+
+    bytes.try_write::<_, LittleEndian>(Blobs { len: 0, first: vec![0; 255], second: vec![1] }).unwrap();
+    assert_eq!(bytes.len(), 258);
+    assert_eq!(bytes[0], 255);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Doubled {
+    value: u8,
+    #[instructor(calc = "value * 2", verify_calc)]
+    double: u8
+}
+
+#[test]
+fn calc_is_filled_in_and_verified() {
+    assert_eq!(write(&Doubled { value: 3, double: 0 }), [0x03, 0x06]);
+    assert_eq!(read::<Doubled>(&[0x03, 0x06]).unwrap(), Doubled { value: 3, double: 6 });
+    let error = read::<Doubled>(&[0x03, 0x07]).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["double"]);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Checked {
+    a: u8,
+    b: u8,
+    #[instructor(checksum = Xor)]
+    check: u8,
+    c: u8,
+    #[instructor(checksum = Xor, covers = "a..=c")]
+    inner: u8
+}
+
+#[test]
+fn checksums_are_computed_and_verified() {
+    let bytes = write(&Checked { a: 0x0f, b: 0xf0, check: 0, c: 0x01, inner: 0 });
+    assert_eq!(bytes, [0x0f, 0xf0, 0xff, 0x01, 0x01]);
+    assert_eq!(read::<Checked>(&bytes).unwrap().check, 0xff);
+    let error = read::<Checked>(&[0x0f, 0xf0, 0xfe, 0x01, 0x01]).unwrap_err();
+    assert_eq!(error, ErrorKind::ChecksumMismatch);
+    assert_eq!(error.fields(), ["check"]);
+    assert_eq!(read::<Checked>(&[0x0f, 0xf0, 0xff, 0x01, 0x02]).unwrap_err(), ErrorKind::ChecksumMismatch);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Skipped {
+    id: u8,
+    #[instructor(skip)]
+    cache: Option<String>,
+    #[instructor(skip, default = "u16::from(id) + 1")]
+    next: u16
+}
+
+#[test]
+fn skipped_fields_are_not_serialized() {
+    let bytes = write(&Skipped { id: 4, cache: Some(String::from("cached")), next: 0 });
+    assert_eq!(bytes, [0x04]);
+    assert_eq!(read::<Skipped>(&bytes).unwrap(), Skipped { id: 4, cache: None, next: 5 });
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Mapped {
+    #[instructor(map = "u32")]
+    addr: std::net::Ipv4Addr,
+    #[instructor(try_map = "u16")]
+    metric: std::num::NonZeroU16
+}
+
+#[test]
+fn mapped_fields_are_converted() {
+    let value = Mapped { addr: std::net::Ipv4Addr::new(10, 0, 0, 1), metric: std::num::NonZeroU16::new(3).unwrap() };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x01, 0x00, 0x00, 0x0a, 0x03, 0x00]);
+    assert_eq!(read::<Mapped>(&bytes).unwrap(), value);
+    let error = read::<Mapped>(&[0x01, 0x00, 0x00, 0x0a, 0x00, 0x00]).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["metric"]);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Header {
+    id: u8,
+    code: Code,
+    len: u16
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Packet {
+    version: u16,
+    header: Header,
+    #[instructor(bitfield(u8))]
+    #[instructor(bits(0..4))]
+    low: u8,
+    #[instructor(bits(4..8))]
+    code: Code
+}
+
+#[test]
+fn errors_point_at_the_start_of_the_field() {
+    let error = read::<Header>(&[0x01, 0x09, 0x00, 0x00]).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid value 0x9 in Header.code at offset 1");
+    let error = read::<Header>(&[0x01, 0x01, 0x00]).unwrap_err();
+    assert_eq!(error.to_string(), "Packet too short in Header.len at offset 2");
+}
+
+#[test]
+fn nested_errors_point_at_the_start_of_the_innermost_field() {
+    let error = read::<Packet>(&[0x01, 0x00, 0x01, 0x09, 0x00, 0x00, 0x00]).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid value 0x9 in Packet.header.code at offset 3");
+    let error = read::<Packet>(&[0x01, 0x00, 0x01, 0x01, 0x00, 0x00, 0x41]).unwrap_err();
+    assert_eq!(error.to_string(), "Invalid value 0x4 in Packet.code at offset 6");
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(endian = "big")]
+struct Ipv4Header {
+    version_ihl: u8,
+    tos: u8,
+    total_length: u16,
+    identification: u16,
+    flags_fragment: u16,
+    ttl: u8,
+    protocol: u8,
+    #[instructor(checksum = Internet, covers = "version_ihl..=destination")]
+    checksum: u16,
+    source: u32,
+    destination: u32,
+    #[instructor(checksum = Xor)]
+    trailer: u8
+}
+
+const IPV4_HEADER: [u8; 21] = [
+    0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0xb8, 0x61, 0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7, 0x38
+];
+
+#[test]
+fn checksums_can_cover_their_own_field() {
+    let header = Ipv4Header {
+        version_ihl: 0x45,
+        tos: 0,
+        total_length: 0x73,
+        identification: 0,
+        flags_fragment: 0x4000,
+        ttl: 0x40,
+        protocol: 0x11,
+        checksum: 0,
+        source: 0xc0a8_0001,
+        destination: 0xc0a8_00c7,
+        trailer: 0
+    };
+    let mut bytes = Vec::new();
+    bytes.write_ref::<_, instructor::BigEndian>(&header);
+    assert_eq!(bytes, IPV4_HEADER);
+    let read = (&bytes[..]).read::<Ipv4Header, instructor::BigEndian>().unwrap();
+    assert_eq!(read, Ipv4Header { checksum: 0xb861, trailer: 0x38, ..header });
+
+    let mut corrupted = IPV4_HEADER;
+    corrupted[8] = 0x3f;
+    corrupted[20] ^= 0x40 ^ 0x3f;
+    let error = (&corrupted[..]).read::<Ipv4Header, instructor::BigEndian>().unwrap_err();
+    assert_eq!(error, ErrorKind::ChecksumMismatch);
+    assert_eq!(error.fields(), ["checksum"]);
+    assert_eq!(error.offset(), Some(10));
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Celsius(u16);
+
+impl From<Inner> for Celsius {
+    fn from(inner: Inner) -> Self {
+        Celsius(inner.b)
+    }
+}
+
+impl From<Celsius> for Inner {
+    fn from(celsius: Celsius) -> Self {
+        Inner { a: 0xc0, b: celsius.0 }
+    }
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct MappedPayload {
+    len: u8,
+    #[instructor(byte_len = "len", map = "Inner")]
+    temperature: Celsius,
+    #[instructor(byte_len_prefix = u8, map = "Inner")]
+    limit: Celsius
+}
+
+#[test]
+fn sized_fields_are_mapped_before_being_measured() {
+    let value = MappedPayload { len: 0, temperature: Celsius(0x0102), limit: Celsius(0x0304) };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x03, 0xc0, 0x02, 0x01, 0x03, 0xc0, 0x04, 0x03]);
+    assert_eq!(read::<MappedPayload>(&bytes).unwrap(), MappedPayload { len: 3, ..value });
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Narrow {
+    #[instructor(bitfield(u8))]
+    #[instructor(bits(0..3))]
+    v: i8,
+    #[instructor(bits(3..7))]
+    w: u8,
+    #[instructor(bits(7..8))]
+    flag: bool
+}
+
+#[test]
+fn bitfield_members_are_validated_before_writing() {
+    let value = Narrow { v: -4, w: 15, flag: true };
+    let mut bytes = Vec::new();
+    bytes.try_write_ref::<_, LittleEndian>(&value).unwrap();
+    assert_eq!(bytes, [0b1111_1100]);
+    assert_eq!(read::<Narrow>(&bytes).unwrap(), value);
+
+    let error = Vec::new().try_write::<_, LittleEndian>(Narrow { v: 4, ..value }).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["v"]);
+    let error = Vec::new().try_write::<_, LittleEndian>(Narrow { w: 16, ..value }).unwrap_err();
+    assert_eq!(error.fields(), ["w"]);
+}
+
+#[test]
+#[should_panic(expected = "Value does not fit into 4 bits")]
+fn writing_bitfield_members_that_dont_fit_panics() {
+    write(&Narrow { v: 0, w: 16, flag: false });
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Exstruct, Instruct)]
+#[repr(u8)]
+enum Opcode {
+    Nop = 0x00,
+    #[instructor(range = 0x10..0x20)]
+    Vendor(u8)
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Instruction {
+    opcode: Opcode,
+    #[instructor(bitfield(u8))]
+    #[instructor(bits(0..6))]
+    operand: Opcode,
+    #[instructor(bits(6..8))]
+    mode: u8
+}
+
+#[test]
+fn range_variants_are_validated_before_writing() {
+    let value = Instruction { opcode: Opcode::Vendor(0x1f), operand: Opcode::Vendor(0x10), mode: 2 };
+    let mut bytes = Vec::new();
+    bytes.try_write_ref::<_, LittleEndian>(&value).unwrap();
+    assert_eq!(bytes, [0x1f, 0x90]);
+    assert_eq!(read::<Instruction>(&bytes).unwrap(), value);
+
+    let error = Vec::new().try_write::<_, LittleEndian>(Opcode::Vendor(0x20)).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.value(), Some(0x20));
+    let error = Vec::new().try_write::<_, LittleEndian>(Instruction { opcode: Opcode::Vendor(0x01), ..value }).unwrap_err();
+    assert_eq!(error.fields(), ["opcode"]);
+    let error = Vec::new().try_write::<_, LittleEndian>(Instruction { operand: Opcode::Vendor(0x01), ..value }).unwrap_err();
+    assert_eq!(error.fields(), ["operand"]);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Exstruct, Instruct, IntEnum)]
+#[repr(u16)]
+enum Status {
+    Ok = 200,
+    #[instructor(values(301, 308))]
+    Moved,
+    #[instructor(range = 400..500)]
+    ClientError(u16)
+}
+
+#[test]
+fn int_enums_are_converted_to_and_from_their_repr() {
+    assert_eq!(Status::try_from(308).unwrap(), Status::Moved);
+    assert_eq!(Status::try_from(404).unwrap(), Status::ClientError(404));
+    assert_eq!(Status::try_from(500).unwrap_err(), ErrorKind::InvalidValue);
+    assert_eq!(u16::from(Status::Moved), 301);
+    assert_eq!(u16::from(&Status::ClientError(418)), 418);
+    // out of range values are only rejected when writing
+    assert_eq!(u16::from(Status::ClientError(200)), 200);
+    assert_eq!(Status::VARIANTS, [Status::Ok, Status::Moved]);
+    assert_eq!(read::<Status>(&[0x94, 0x01]).unwrap(), Status::ClientError(404));
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[repr(u8)]
+enum Level {
+    Low = 1,
+    High = 2
+}
+
+// conversions are only implemented by `IntEnum`, so they can be written by hand as well
+impl From<Level> for u8 {
+    fn from(level: Level) -> u8 {
+        level as u8 * 10
+    }
+}
+
+#[test]
+fn int_enums_can_implement_conversions_by_hand() {
+    assert_eq!(u8::from(Level::High), 20);
+    assert_eq!(write(&Level::High), [2]);
+    assert_eq!(read::<Level>(&[1]).unwrap(), Level::Low);
+}
+
+bitflags! {
+    #[derive(Debug, Copy, Clone, PartialEq, Exstruct, Instruct)]
+    #[instructor(bitflags)]
+    struct Permissions: u8 {
+        const READ = 0b001;
+        const WRITE = 0b010;
+        const EXECUTE = 0b100;
+    }
+
+    #[derive(Debug, Copy, Clone, PartialEq, Exstruct, Instruct)]
+    #[instructor(endian = "little", bitflags(truncate))]
+    struct Options: u16 {
+        const VERBOSE = 0x0001;
+        const STRICT = 0x0100;
+    }
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Mode {
+    #[instructor(bitfield(u32))]
+    #[instructor(bits(0..3))]
+    owner: Permissions,
+    #[instructor(bits(3..6))]
+    group: Permissions,
+    #[instructor(bits(6..22), endian = "little")]
+    options: Options,
+    #[instructor(bits(22..32))]
+    rest: u16
+}
+
+#[test]
+fn bitflags_can_be_bitfield_members() {
+    let value = Mode {
+        owner: Permissions::READ | Permissions::WRITE,
+        group: Permissions::EXECUTE,
+        options: Options::STRICT | Options::VERBOSE,
+        rest: 0x3ff
+    };
+    let mut bytes = Vec::new();
+    bytes.try_write_ref::<_, LittleEndian>(&value).unwrap();
+    assert_eq!(bytes, 0xffc0_4063u32.to_le_bytes());
+    assert_eq!(read::<Mode>(&bytes).unwrap(), value);
+
+    // the bitflags mode applies within a bitfield as well
+    let truncated = read::<Mode>(&0x0000_0100u32.to_le_bytes()).unwrap();
+    assert_eq!(truncated.options, Options::empty());
+}
+
+mod shadowed {
+    use instructor::checksum::Xor;
+    use instructor_derive::{Exstruct, Instruct};
+
+    // generated code must not pick up user types named like the ones it uses
+    #[allow(dead_code)]
+    pub struct Vec;
+
+    #[derive(Instruct)]
+    pub struct Prefixed {
+        #[instructor(byte_len_prefix = u8)]
+        pub inner: u16
+    }
+
+    #[derive(Exstruct, Instruct)]
+    pub struct Covered {
+        pub a: u8,
+        #[instructor(checksum = Xor, covers = "a..=b")]
+        pub check: u8,
+        pub b: u8
+    }
+}
+
+#[test]
+fn generated_code_ignores_shadowed_names() {
+    assert_eq!(write(&shadowed::Prefixed { inner: 0x0102 }), [0x02, 0x02, 0x01]);
+    let bytes = write(&shadowed::Covered { a: 0x0f, check: 0, b: 0x30 });
+    assert_eq!(bytes, [0x0f, 0x3f, 0x30]);
+    assert_eq!(read::<shadowed::Covered>(&bytes).unwrap().check, 0x3f);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(tag = Code)]
+enum Command {
+    #[instructor(tag_value = Code::Echo)]
+    Run(Opcode),
+    #[instructor(tag_value = Code::Info)]
+    Stop
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Vendor(u8);
+
+impl From<Opcode> for Vendor {
+    fn from(opcode: Opcode) -> Self {
+        match opcode {
+            Opcode::Nop => Self(0),
+            Opcode::Vendor(value) => Self(value)
+        }
+    }
+}
+
+impl From<Vendor> for Opcode {
+    fn from(vendor: Vendor) -> Self {
+        Opcode::Vendor(vendor.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Scheduled {
+    code: Code,
+    #[instructor(select = "code")]
+    command: Command,
+    #[instructor(map = "Opcode")]
+    vendor: Vendor
+}
+
+#[test]
+fn tagged_and_mapped_fields_are_validated_before_writing() {
+    let value = Scheduled { code: Code::Echo, command: Command::Run(Opcode::Vendor(0x11)), vendor: Vendor(0x12) };
+    let mut bytes = Vec::new();
+    bytes.try_write_ref::<_, LittleEndian>(&value).unwrap();
+    assert_eq!(bytes, [0x01, 0x11, 0x12]);
+    assert_eq!(read::<Scheduled>(&bytes).unwrap(), value);
+
+    let error = Vec::new().try_write::<_, LittleEndian>(Scheduled { command: Command::Run(Opcode::Vendor(0x01)), ..value }).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["command", "0"]);
+    let error = Vec::new().try_write::<_, LittleEndian>(Scheduled { vendor: Vendor(0x01), ..value }).unwrap_err();
+    assert_eq!(error.fields(), ["vendor"]);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Event {
+    version: u8,
+    #[instructor(if = "version >= 2")]
+    timestamp: Option<u16>,
+    tail: u8
+}
+
+#[test]
+fn conditional_fields_are_read_if_their_condition_holds() {
+    let present = Event { version: 2, timestamp: Some(0x0102), tail: 9 };
+    let bytes = write(&present);
+    assert_eq!(bytes, [0x02, 0x02, 0x01, 0x09]);
+    assert_eq!(read::<Event>(&bytes).unwrap(), present);
+
+    let absent = Event { version: 1, timestamp: None, tail: 9 };
+    let bytes = write(&absent);
+    assert_eq!(bytes, [0x01, 0x09]);
+    assert_eq!(read::<Event>(&bytes).unwrap(), absent);
+}
+
+#[test]
+fn conditional_fields_must_match_their_condition() {
+    let mut bytes = Vec::new();
+    let error = bytes.try_write::<_, LittleEndian>(Event { version: 1, timestamp: Some(5), tail: 9 }).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["timestamp"]);
+    let error = bytes.try_write::<_, LittleEndian>(Event { version: 2, timestamp: None, tail: 9 }).unwrap_err();
+    assert_eq!(error.fields(), ["timestamp"]);
+    assert!(bytes.is_empty());
+}
+
+#[test]
+#[should_panic(expected = "`timestamp` must be present exactly if its condition holds")]
+fn writing_conditional_fields_that_dont_match_panics() {
+    write(&Event { version: 1, timestamp: Some(5), tail: 9 });
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Frame<T> {
+    len: u8,
+    body: T
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+struct Marker;
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(bound = "T: Copy")]
+struct Marked<T> {
+    len: u16,
+    marker: std::marker::PhantomData<T>
+}
+
+#[test]
+fn generic_types_are_read_and_written() {
+    let value = Frame { len: 2, body: Frame { len: 1, body: 0x0304u16 } };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x02, 0x01, 0x04, 0x03]);
+    assert_eq!(read::<Frame<Frame<u16>>>(&bytes).unwrap(), value);
+
+    // `Marker` doesn't implement the traits itself, the bound only asks for `Copy`
+    let value = Marked::<Marker> { len: 0x0102, marker: std::marker::PhantomData };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x02, 0x01]);
+    assert_eq!(read::<Marked<Marker>>(&bytes).unwrap(), value);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(endian = "little")]
+struct Mixed {
+    id: u16,
+    #[instructor(endian = "big")]
+    vendor: u32,
+    #[instructor(bitfield(u16), endian = "big")]
+    #[instructor(bits(0..4))]
+    low: u8,
+    #[instructor(bits(4..16))]
+    high: u16
+}
+
+#[test]
+fn fields_can_override_the_endianness() {
+    let value = Mixed { id: 0x0102, vendor: 0x03040506, low: 0x7, high: 0x089 };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x02, 0x01, 0x03, 0x04, 0x05, 0x06, 0x08, 0x97]);
+    assert_eq!(read::<Mixed>(&bytes).unwrap(), value);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(magic = b"\xca\xfe")]
+struct Signed {
+    #[instructor(const = 2)]
+    version: u16,
+    len: u8
+}
+
+#[test]
+fn constants_are_written_and_checked() {
+    let value = Signed { version: 7, len: 3 };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0xca, 0xfe, 0x02, 0x00, 0x03]);
+    assert_eq!(read::<Signed>(&bytes).unwrap(), Signed { version: 2, len: 3 });
+
+    let error = read::<Signed>(&[0xca, 0xff, 0x02, 0x00, 0x03]).unwrap_err();
+    assert_eq!(error, ErrorKind::ConstantMismatch);
+    assert_eq!(error.fields(), ["magic"]);
+    let error = read::<Signed>(&[0xca, 0xfe, 0x03, 0x00, 0x03]).unwrap_err();
+    assert_eq!(error, ErrorKind::ConstantMismatch);
+    assert_eq!(error.fields(), ["version"]);
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(align = 8, pad_byte = 0xff, strict_padding)]
+struct Reserved {
+    kind: u8,
+    #[instructor(pad_before = 1)]
+    len: u16,
+    #[instructor(pad_after = 2)]
+    flags: u8
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Lenient {
+    #[instructor(pad_before = 1)]
+    len: u16
+}
+
+#[test]
+fn padding_is_written_and_skipped() {
+    let value = Reserved { kind: 1, len: 0x0203, flags: 4 };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x01, 0xff, 0x03, 0x02, 0x04, 0xff, 0xff, 0xff]);
+    assert_eq!(read::<Reserved>(&bytes).unwrap(), value);
+
+    let error = read::<Reserved>(&[0x01, 0xff, 0x03, 0x02, 0x04, 0xff, 0x00, 0xff]).unwrap_err();
+    assert_eq!(error, ErrorKind::ConstantMismatch);
+    assert_eq!(error.value(), Some(0));
+
+    assert_eq!(write(&Lenient { len: 0x0102 }), [0x00, 0x02, 0x01]);
+    assert_eq!(read::<Lenient>(&[0x7f, 0x02, 0x01]).unwrap(), Lenient { len: 0x0102 });
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+#[instructor(validate = "validate_range")]
+struct PortRange {
+    #[instructor(validate = "validate_port")]
+    start: u16,
+    end: u16
+}
+
+fn validate_port(port: &u16) -> Result<(), instructor::Error> {
+    match *port {
+        0 => Err(instructor::Error::invalid_value(*port)),
+        _ => Ok(())
+    }
+}
+
+fn validate_range(range: &PortRange) -> Result<(), ErrorKind> {
+    match range.start <= range.end {
+        true => Ok(()),
+        false => Err(ErrorKind::InvalidValue)
+    }
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Listener {
+    id: u8,
+    ports: PortRange
+}
+
+#[test]
+fn validation_errors_name_the_field() {
+    let value = Listener { id: 1, ports: PortRange { start: 80, end: 443 } };
+    let bytes = write(&value);
+    assert_eq!(read::<Listener>(&bytes).unwrap(), value);
+
+    let error = read::<Listener>(&[0x01, 0x00, 0x00, 0xbb, 0x01]).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["ports", "start"]);
+    assert_eq!(error.value(), Some(0));
+    let error = read::<Listener>(&[0x01, 0xbb, 0x01, 0x50, 0x00]).unwrap_err();
+    assert_eq!(error, ErrorKind::InvalidValue);
+    assert_eq!(error.fields(), ["ports"]);
+
+    let error = Vec::new().try_write::<_, LittleEndian>(Listener { id: 1, ports: PortRange { start: 0, end: 443 } }).unwrap_err();
+    assert_eq!(error.fields(), ["ports", "start"]);
+    let error = Vec::new().try_write::<_, LittleEndian>(Listener { id: 1, ports: PortRange { start: 443, end: 80 } }).unwrap_err();
+    assert_eq!(error.fields(), ["ports"]);
+}
+
+mod centi {
+    use instructor::{Buffer, BufferMut, Endian, Error};
+
+    pub fn read<E: Endian, B: Buffer>(buffer: &mut B) -> Result<f32, Error> {
+        Ok(f32::from(buffer.read::<i16, E>()?) / 100.0)
+    }
+
+    pub fn write<E: Endian, B: BufferMut>(value: &f32, buffer: &mut B) {
+        buffer.write::<i16, E>((value * 100.0) as i16);
+    }
+}
+
+#[derive(Debug, PartialEq, Exstruct, Instruct)]
+struct Measurement {
+    #[instructor(with = "centi")]
+    temperature: f32,
+    #[instructor(read_with = "centi::read", write_with = "centi::write")]
+    humidity: f32
+}
+
+#[test]
+fn fields_can_use_custom_functions() {
+    let value = Measurement { temperature: -2.5, humidity: 40.0 };
+    let bytes = write(&value);
+    assert_eq!(bytes, [0x06, 0xff, 0xa0, 0x0f]);
+    assert_eq!(read::<Measurement>(&bytes).unwrap(), value);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Exstruct, Instruct)]
+#[repr(u8)]
+enum Access {
+    Read = 0x01,
+    Write = 0x02,
+    #[instructor(unknown)]
+    Unknown(u8)
+}
+
+#[test]
+fn unknown_values_round_trip() {
+    assert_eq!(read::<Access>(&[0x02]).unwrap(), Access::Write);
+    assert_eq!(read::<Access>(&[0x7f]).unwrap(), Access::Unknown(0x7f));
+    assert_eq!(write(&Access::Read), [0x01]);
+    assert_eq!(write(&Access::Unknown(0x7f)), [0x7f]);
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Exstruct, Instruct)]
+#[repr(u8)]
+enum Step {
+    Reset,
+    Status,
+    Read = 0x10,
+    Write,
+    #[instructor(values(0x20, 0x21))]
+    Erase
+}
+
+#[test]
+fn implicit_discriminants_count_up() {
+    for (step, byte) in [(Step::Reset, 0x00), (Step::Status, 0x01), (Step::Read, 0x10), (Step::Write, 0x11), (Step::Erase, 0x20)] {
+        assert_eq!(write(&step), [byte]);
+        assert_eq!(read::<Step>(&[byte]).unwrap(), step);
+    }
+    assert_eq!(read::<Step>(&[0x21]).unwrap(), Step::Erase);
+    assert_eq!(read::<Step>(&[0x12]).unwrap_err(), ErrorKind::InvalidValue);
+}